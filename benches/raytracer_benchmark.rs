@@ -1,22 +1,21 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 
 use rayon::prelude::*;
 
 use raytracer::camera::Camera;
 use raytracer::materials;
-use raytracer::render::ray_color;
-use raytracer::shapes::hittable::{HitRecord, Hittable};
+use raytracer::render::{ray_color, Background};
+use raytracer::shapes::bvh::BvhNode;
+use raytracer::shapes::hittable::Hittable;
 use raytracer::shapes::hittable_list::HittableList;
 use raytracer::shapes::sphere::Sphere;
-use raytracer::utils;
+use raytracer::utils::{mix_seed, Rng};
 use raytracer::vec3::{Color, Point3, Vec3};
 use std::sync::Arc;
 
 fn random_scene() -> HittableList {
     let mut world = HittableList::new();
-    let material_ground = materials::lambertian::Lambertian {
-        albedo: Color::new(0.5, 0.5, 0.5),
-    };
+    let material_ground = materials::lambertian::Lambertian::new(Color::new(0.5, 0.5, 0.5));
 
     world.add(Box::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
@@ -24,39 +23,41 @@ fn random_scene() -> HittableList {
         Some(Arc::new(material_ground)),
     )));
 
-    for a in -11..11 {
-        let af = a as f64;
-        let bf = a as f64;
-
-        let choose_mat = 10.0 / (1 + a % 10) as f64;
-
-        let center = Point3::new(af + 0.9, 0.2, bf + 0.9);
-        if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-            let sphere_material: Option<Arc<dyn materials::Material + Sync + Send>> =
-                if choose_mat < 0.8 {
-                    // Diffuse
-                    let albedo = Color::new(0.5, 0.7, 0.0);
-                    Some(Arc::new(materials::lambertian::Lambertian { albedo }))
-                } else if choose_mat < 0.95 {
-                    // Metal
-
-                    let albedo = Color::new(0.5, 0.7, 0.0);
-                    let fuzz = 0.3;
-
-                    Some(Arc::new(materials::metal::Metal { albedo, fuzz }))
-                } else {
-                    // Glass
-                    Some(Arc::new(materials::dielectric::Dielectric { ref_idx: 1.5 }))
-                };
-
-            world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+    for a in -11i32..11 {
+        for b in -11..11 {
+            let af = a as f64;
+            let bf = b as f64;
+
+            let choose_mat = (a * 7 + b * 13).rem_euclid(20) as f64 / 20.0;
+
+            let center = Point3::new(af + 0.9, 0.2, bf + 0.9);
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                let sphere_material: Option<Arc<dyn materials::Material + Sync + Send>> =
+                    if choose_mat < 0.8 {
+                        // Diffuse
+                        let albedo = Color::new(0.5, 0.7, 0.0);
+                        Some(Arc::new(materials::lambertian::Lambertian::new(albedo)))
+                    } else if choose_mat < 0.95 {
+                        // Metal
+
+                        let albedo = Color::new(0.5, 0.7, 0.0);
+                        let fuzz = 0.3;
+
+                        Some(Arc::new(materials::metal::Metal::new(albedo, fuzz)))
+                    } else {
+                        // Glass
+                        Some(Arc::new(materials::dielectric::Dielectric { ref_idx: 1.5 }))
+                    };
+
+                world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
+            }
         }
     }
 
     world
 }
 
-fn render() {
+fn render(world: &(dyn Hittable + Send + Sync)) {
     const ASPECT_RATIO: f64 = 16.0 / 9.0;
 
     // Image
@@ -65,9 +66,6 @@ fn render() {
     let samples_per_pixel = 15;
     let max_depth = 50;
 
-    // World
-    let world = random_scene();
-
     // Camera
     let lookfrom = Point3::new(13.0, 2.0, 3.0);
     let lookat = Point3::new(0.0, 0.0, 0.0);
@@ -80,31 +78,42 @@ fn render() {
         ASPECT_RATIO,
         0.1,
         dist_to_focus,
+        0.0,
+        0.0,
     );
 
     for j in (0..image_height).rev() {
         (0..image_width)
             .into_par_iter()
             .map(|i| {
+                let mut rng = Rng::new(mix_seed(j as u64, i as u64));
                 let mut pixel_color = Color::zero();
-                for s in 0..samples_per_pixel {
-                    let u = (i as f64 + utils::random()) / (image_width - 1) as f64;
-                    let v = (j as f64 + utils::random()) / (image_height - 1) as f64;
-                    let r = cam.get_ray(u, v);
-                    pixel_color += ray_color(&r, &world, max_depth);
+                for _s in 0..samples_per_pixel {
+                    let u = (i as f64 + rng.random()) / (image_width - 1) as f64;
+                    let v = (j as f64 + rng.random()) / (image_height - 1) as f64;
+                    let r = cam.get_ray(u, v, &mut rng);
+                    pixel_color +=
+                        ray_color(&r, &Background::Sky, world, None, max_depth, &mut rng);
                 }
                 pixel_color
             })
             .collect::<Vec<Color>>()
             .into_iter()
-            .for_each(|pixel_color| {
+            .for_each(|_pixel_color| {
                 // DO NOTHING
             });
     }
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("render", |b| b.iter(|| render()));
+    let list = random_scene();
+    let bvh = BvhNode::new(random_scene(), 0.0, 0.0);
+
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    group.bench_function("list", |b| b.iter(|| render(&list)));
+    group.bench_function("bvh", |b| b.iter(|| render(&bvh)));
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
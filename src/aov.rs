@@ -0,0 +1,379 @@
+//! Arbitrary output variables: images of what the camera rays hit first,
+//! and of the light split into direct and indirect, for compositing and
+//! denoising.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::image::exr::Channel;
+use crate::image::{srgb_to_linear, Image};
+use crate::ray::Ray;
+use crate::render::Background;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::shapes::hittable::{HitRecord, Hittable};
+use crate::utils::mix_seed;
+use crate::vec3::{Color, Vec3};
+
+/// Samples per pixel for the AOVs of the first hit. They only take one ray
+/// each, so they are quick, and don't need many to be smooth.
+pub const FIRST_HIT_SAMPLES: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    /// Distance from the camera, the nearest of the samples. Infinite where
+    /// nothing was hit.
+    Depth,
+    /// World space normal, facing the camera.
+    Normal,
+    /// Surface color without lighting, the background's color where nothing
+    /// was hit.
+    Albedo,
+    /// Which object in the scene was hit, numbered in the order they were
+    /// added from 1. 0 for the background.
+    ObjectId,
+    /// Which material was hit, numbered from 1 in the order they first
+    /// appear, going row by row from the top left. 0 for the background.
+    MaterialId,
+    /// Surface coordinates.
+    Uv,
+    /// Light seen straight from its source, or after one bounce.
+    Direct,
+    /// Light that bounced more than once.
+    Indirect,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 8] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Uv,
+        Aov::Direct,
+        Aov::Indirect,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object-id",
+            Aov::MaterialId => "material-id",
+            Aov::Uv => "uv",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    /// Channel names in an EXR layer. They are stored in an `Image` in that
+    /// order, with single values as gray.
+    pub fn channel_names(self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal => &["X", "Y", "Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Uv => &["U", "V"],
+            Aov::Albedo | Aov::Direct | Aov::Indirect => &["R", "G", "B"],
+        }
+    }
+
+    /// The channels of `image`, the values of this AOV, as an EXR layer.
+    pub fn channels(self, image: &Image) -> Vec<Channel> {
+        self.channel_names()
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Channel {
+                name: format!("{}.{}", self.name(), name),
+                values: image
+                    .pixels
+                    .iter()
+                    .map(|c| [c.x, c.y, c.z][i] as f32)
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Makes the values of this AOV viewable as an 8-bit image: depth
+    /// from black at the camera to white at the furthest point, normals and
+    /// UVs as the usual colors, and a random color for each ID. Light is left
+    /// alone, it should be tone mapped like the render.
+    pub fn display(self, image: &Image) -> Image {
+        let mut display = image.clone();
+        let encoded = |c: Color| {
+            Color::new(
+                srgb_to_linear(c.x.clamp(0.0, 1.0)),
+                srgb_to_linear(c.y.clamp(0.0, 1.0)),
+                srgb_to_linear(c.z.clamp(0.0, 1.0)),
+            )
+        };
+        match self {
+            Aov::Depth => {
+                let furthest = image
+                    .pixels
+                    .iter()
+                    .map(|c| c.x)
+                    .filter(|d| d.is_finite())
+                    .fold(0.0, f64::max);
+                for pixel in &mut display.pixels {
+                    let d = if pixel.x.is_finite() && furthest > 0.0 {
+                        pixel.x / furthest
+                    } else {
+                        1.0
+                    };
+                    *pixel = encoded(Color::new(d, d, d));
+                }
+            }
+            Aov::Normal => {
+                for pixel in &mut display.pixels {
+                    *pixel = encoded(0.5 * (*pixel + Color::ones()));
+                }
+            }
+            Aov::Uv => {
+                for pixel in &mut display.pixels {
+                    *pixel = encoded(*pixel);
+                }
+            }
+            Aov::ObjectId | Aov::MaterialId => {
+                for pixel in &mut display.pixels {
+                    *pixel = id_color(pixel.x as u64);
+                }
+            }
+            Aov::Albedo | Aov::Direct | Aov::Indirect => {}
+        }
+        display
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Aov, String> {
+        Aov::ALL
+            .iter()
+            .copied()
+            .find(|aov| aov.name() == s)
+            .ok_or_else(|| {
+                let names = Aov::ALL.map(|aov| aov.name());
+                format!("unknown AOV `{}`, use one of {}", s, names.join(", "))
+            })
+    }
+}
+
+/// A bright color for an ID, black for 0.
+fn id_color(id: u64) -> Color {
+    if id == 0 {
+        return Color::zero();
+    }
+    let hash = mix_seed(0x1d, id);
+    let channel = |shift: u32| 0.2 + 0.8 * ((hash >> shift) & 0xff) as f64 / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+/// What the camera rays hit first, for every pixel. Pixels are stored like
+/// in `Image`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FirstHits {
+    pub width: usize,
+    pub height: usize,
+    pub depth: Vec<f64>,
+    pub normal: Vec<Vec3>,
+    pub albedo: Vec<Color>,
+    pub object_id: Vec<u32>,
+    pub material_id: Vec<u32>,
+    pub uv: Vec<(f64, f64)>,
+}
+
+impl FirstHits {
+    /// Traces `FIRST_HIT_SAMPLES` rays for every pixel, with samplers of
+    /// `kind` seeded with `seed`. `camera_ray` gives the ray for the pixel at
+    /// `x`, `y`, counting from the top left, taking what it needs from the
+    /// sampler. Object IDs come from `Tagged` objects in `world`.
+    pub fn render<F>(
+        width: usize,
+        height: usize,
+        kind: SamplerKind,
+        seed: u64,
+        background: &Background,
+        world: &(dyn Hittable + Sync),
+        camera_ray: F,
+    ) -> FirstHits
+    where
+        F: Fn(usize, usize, &mut dyn Sampler) -> Ray + Sync,
+    {
+        let pixels = (0..width * height)
+            .into_par_iter()
+            .map(|index| {
+                let (x, y) = (index % width, index / width);
+                let mut sampler = kind.create(seed, FIRST_HIT_SAMPLES);
+                let mut pixel = Pixel {
+                    depth: f64::INFINITY,
+                    normal: Vec3::zero(),
+                    albedo: Color::zero(),
+                    object_id: 0,
+                    material: 0,
+                    uv: (0.0, 0.0),
+                };
+                for n in 0..FIRST_HIT_SAMPLES {
+                    sampler.start_pixel_sample(x as u32, y as u32, n);
+                    let r = camera_ray(x, y, &mut *sampler);
+                    let mut rec = HitRecord::new();
+                    if !world.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut *sampler) {
+                        pixel.albedo += background.value(&r);
+                        continue;
+                    }
+
+                    pixel.depth = pixel.depth.min(rec.t * r.direction.length());
+                    pixel.normal += rec.normal;
+                    pixel.uv = (pixel.uv.0 + rec.u, pixel.uv.1 + rec.v);
+                    if let Some(material) = &rec.mat_ptr {
+                        pixel.albedo += material.albedo(&rec);
+                    }
+                    // IDs can't be averaged, they come from the first sample.
+                    if n == 0 {
+                        pixel.object_id = rec.object_id;
+                        pixel.material = rec
+                            .mat_ptr
+                            .as_ref()
+                            .map_or(0, |material| Arc::as_ptr(material) as *const () as usize);
+                    }
+                }
+                pixel
+            })
+            .collect::<Vec<_>>();
+
+        // Materials are only known by their address, which changes from run
+        // to run, so they are numbered by where they first appear instead.
+        let mut material_ids = HashMap::new();
+        material_ids.insert(0, 0);
+        let mut material_id = Vec::with_capacity(pixels.len());
+        for pixel in &pixels {
+            let next = material_ids.len() as u32;
+            material_id.push(*material_ids.entry(pixel.material).or_insert(next));
+        }
+
+        let n = FIRST_HIT_SAMPLES as f64;
+        FirstHits {
+            width,
+            height,
+            depth: pixels.iter().map(|p| p.depth).collect(),
+            normal: pixels.iter().map(|p| p.normal / n).collect(),
+            albedo: pixels.iter().map(|p| p.albedo / n).collect(),
+            object_id: pixels.iter().map(|p| p.object_id).collect(),
+            material_id,
+            uv: pixels.iter().map(|p| (p.uv.0 / n, p.uv.1 / n)).collect(),
+        }
+    }
+
+    /// The values of `aov` as an image, `None` for the ones about light.
+    pub fn image(&self, aov: Aov) -> Option<Image> {
+        let gray = |v: f64| Color::new(v, v, v);
+        let pixels = match aov {
+            Aov::Depth => self.depth.iter().map(|d| gray(*d)).collect(),
+            Aov::Normal => self.normal.clone(),
+            Aov::Albedo => self.albedo.clone(),
+            Aov::ObjectId => self.object_id.iter().map(|id| gray(*id as f64)).collect(),
+            Aov::MaterialId => self.material_id.iter().map(|id| gray(*id as f64)).collect(),
+            Aov::Uv => self
+                .uv
+                .iter()
+                .map(|(u, v)| Color::new(*u, *v, 0.0))
+                .collect(),
+            Aov::Direct | Aov::Indirect => return None,
+        };
+        Some(Image {
+            width: self.width,
+            height: self.height,
+            pixels,
+        })
+    }
+}
+
+/// Sums for one pixel while rendering.
+struct Pixel {
+    depth: f64,
+    normal: Vec3,
+    albedo: Color,
+    object_id: u32,
+    material: usize,
+    uv: (f64, f64),
+}
+
+#[test]
+fn test_first_hits() {
+    use crate::materials::lambertian::Lambertian;
+    use crate::shapes::hittable_list::HittableList;
+    use crate::shapes::sphere::Sphere;
+    use crate::shapes::tagged::Tagged;
+    use crate::vec3::Point3;
+
+    // Two spheres side by side with the same material, in front of the sky.
+    let red = Arc::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+    let mut world = HittableList::new();
+    for x in &[-0.5, 0.5] {
+        world.add(Box::new(Sphere::new(
+            Point3::new(*x, 0.0, -3.0),
+            0.3,
+            Some(red.clone()),
+        )));
+    }
+    let world = Tagged::tag_all(world);
+
+    // An orthographic camera looking down -z, one pixel per unit.
+    let hits = FirstHits::render(
+        4,
+        2,
+        SamplerKind::Sobol,
+        1,
+        &Background::Sky,
+        &world,
+        |x, y, sampler| {
+            let (u, v) = sampler.get_2d();
+            let origin = Point3::new(
+                x as f64 - 1.5 + 0.1 * (u - 0.5),
+                0.1 * (v - 0.5) - y as f64,
+                0.0,
+            );
+            Ray::new(origin, Vec3::new(0.0, 0.0, -2.0), 0.0)
+        },
+    );
+
+    // Only the middle of the top row sees the spheres.
+    let index = |x: usize, y: usize| y * 4 + x;
+    let left = index(1, 0);
+    let right = index(2, 0);
+    assert!(
+        (hits.depth[left] - 2.7).abs() < 0.05,
+        "{}",
+        hits.depth[left]
+    );
+    assert_eq!(hits.depth[index(0, 0)], f64::INFINITY);
+    assert!((hits.normal[left] - Vec3::new(0.0, 0.0, 1.0)).length() < 0.1);
+    assert!((hits.albedo[left] - Color::new(0.8, 0.1, 0.1)).length() < 1e-9);
+    let sky = Background::Sky.value(&Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0), 0.0));
+    assert!((hits.albedo[index(0, 1)] - sky).length() < 1e-9);
+    assert_eq!((hits.object_id[left], hits.object_id[right]), (1, 2));
+    assert_eq!(hits.object_id[index(3, 0)], 0);
+    // One material, whichever sphere it's on.
+    assert_eq!((hits.material_id[left], hits.material_id[right]), (1, 1));
+    assert_eq!(hits.material_id[index(0, 0)], 0);
+
+    let depth = hits.image(Aov::Depth).unwrap();
+    let channels = Aov::Depth.channels(&depth);
+    assert_eq!(channels.len(), 1);
+    assert_eq!(channels[0].name, "depth.Z");
+    assert_eq!(channels[0].values[left], hits.depth[left] as f32);
+    assert!(hits.image(Aov::Direct).is_none());
+    assert_eq!("material-id".parse(), Ok(Aov::MaterialId));
+}
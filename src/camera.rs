@@ -1,4 +1,5 @@
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::{Point3, Vec3};
 
 pub struct Camera {
@@ -10,10 +11,15 @@ pub struct Camera {
     pub v: Vec3,
     pub w: Vec3,
     pub lens_radius: f64,
+    /// Shutter open and close times.
+    pub time0: f64,
+    pub time1: f64,
 }
 
 impl Camera {
-    /// vfov is vertical field of view in degrees.
+    /// vfov is vertical field of view in degrees. Rays are sent out at random
+    /// times between time0 and time1 while the shutter is open.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -22,6 +28,8 @@ impl Camera {
         aspect_ratio: f64,
         aperture: f64,
         focus_dist: f64,
+        time0: f64,
+        time1: f64,
     ) -> Camera {
         let theta = vfov.to_radians();
         let h = (theta / 2.0).tan();
@@ -48,16 +56,19 @@ impl Camera {
             v,
             w,
             lens_radius,
+            time0,
+            time1,
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * Vec3::random_in_unit_disk(sampler);
         let offset = self.u * rd.x + self.v * rd.y;
 
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+            self.time0 + (self.time1 - self.time0) * sampler.get_1d(),
         )
     }
 }
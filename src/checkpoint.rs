@@ -0,0 +1,283 @@
+//! Saving a render in progress, so it can be picked up again after a crash
+//! or being stopped.
+//!
+//! The file is binary, little endian throughout: the magic bytes
+//! `RTCHECK3`, the arguments (a u32 count, then each as a u32 length and
+//! UTF-8 bytes), the seed (u64), the next pass (u64), the width and height
+//! (u64), whether direct light is kept apart (u8), and then for every pixel
+//! its `Framebuffer` sum (3 f64s), squared luminance (f64), sample count
+//! (u32) and, if it is, the sum of the direct light (3 f64s).
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::framebuffer::Framebuffer;
+use crate::vec3::Color;
+
+/// Bumped whenever the layout changes, so an older file is refused rather
+/// than misread.
+const MAGIC: &[u8; 8] = b"RTCHECK3";
+
+/// Everything needed to carry on with a render where it left off. Samples
+/// are numbered per pixel, and the sampler gives the same numbers for the
+/// same sample, so the finished image is the same as if the render had never
+/// stopped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    /// The command line the render was started with.
+    pub args: Vec<String>,
+    pub seed: u64,
+    /// The pass to render next.
+    pub pass: u64,
+    pub framebuffer: Framebuffer,
+}
+
+impl Checkpoint {
+    /// Writes to a temporary file next to `path` first, so an interrupted
+    /// save doesn't destroy the previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+
+        let mut w = BufWriter::new(File::create(&temporary)?);
+        self.write(&mut w)?;
+        w.into_inner()?.sync_all()?;
+        fs::rename(&temporary, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Checkpoint> {
+        Checkpoint::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&(self.args.len() as u32).to_le_bytes())?;
+        for arg in &self.args {
+            w.write_all(&(arg.len() as u32).to_le_bytes())?;
+            w.write_all(arg.as_bytes())?;
+        }
+        w.write_all(&self.seed.to_le_bytes())?;
+        w.write_all(&self.pass.to_le_bytes())?;
+
+        let fb = &self.framebuffer;
+        w.write_all(&(fb.width as u64).to_le_bytes())?;
+        w.write_all(&(fb.height as u64).to_le_bytes())?;
+        w.write_all(&[fb.direct.is_some() as u8])?;
+        for i in 0..fb.sum.len() {
+            for value in &[fb.sum[i].x, fb.sum[i].y, fb.sum[i].z, fb.luminance_sq[i]] {
+                w.write_all(&value.to_le_bytes())?;
+            }
+            w.write_all(&fb.samples[i].to_le_bytes())?;
+            if let Some(direct) = &fb.direct {
+                for value in &[direct[i].x, direct[i].y, direct[i].z] {
+                    w.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+        w.flush()
+    }
+
+    pub fn read<R: Read>(mut r: R) -> io::Result<Checkpoint> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a checkpoint file".to_string()));
+        }
+
+        let count = read_u32(&mut r)?;
+        let mut args = Vec::new();
+        for _ in 0..count {
+            let mut bytes = vec![0; read_u32(&mut r)? as usize];
+            r.read_exact(&mut bytes)?;
+            let arg = String::from_utf8(bytes)
+                .map_err(|_| invalid_data("argument isn't valid UTF-8".to_string()))?;
+            args.push(arg);
+        }
+        let seed = read_u64(&mut r)?;
+        let pass = read_u64(&mut r)?;
+
+        let width = read_u64(&mut r)? as usize;
+        let height = read_u64(&mut r)? as usize;
+        if !matches!(width.checked_mul(height), Some(n) if n <= 1 << 30) {
+            return Err(invalid_data(format!(
+                "image size {}x{} is too large",
+                width, height
+            )));
+        }
+        let mut direct = [0];
+        r.read_exact(&mut direct)?;
+        let mut framebuffer = match direct[0] {
+            0 => Framebuffer::new(width, height),
+            1 => Framebuffer::with_direct(width, height),
+            _ => return Err(invalid_data("corrupt checkpoint file".to_string())),
+        };
+        for i in 0..width * height {
+            framebuffer.sum[i] = read_color(&mut r)?;
+            framebuffer.luminance_sq[i] = read_f64(&mut r)?;
+            framebuffer.samples[i] = read_u32(&mut r)?;
+            if let Some(direct) = &mut framebuffer.direct {
+                direct[i] = read_color(&mut r)?;
+            }
+        }
+
+        Ok(Checkpoint {
+            args,
+            seed,
+            pass,
+            framebuffer,
+        })
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(r: &mut R) -> io::Result<f64> {
+    read_u64(r).map(f64::from_bits)
+}
+
+fn read_color<R: Read>(r: &mut R) -> io::Result<Color> {
+    let x = read_f64(r)?;
+    let y = read_f64(r)?;
+    let z = read_f64(r)?;
+    Ok(Color::new(x, y, z))
+}
+
+#[test]
+fn test_checkpoint_round_trip() {
+    let mut framebuffer = Framebuffer::new(3, 2);
+    framebuffer.add(4, Color::new(0.1, 0.2, 1.0 / 3.0), 0.7, 5);
+    let checkpoint = Checkpoint {
+        args: vec![
+            "scene.toml".to_string(),
+            "-s".to_string(),
+            "100".to_string(),
+        ],
+        seed: 42,
+        pass: 7,
+        framebuffer,
+    };
+
+    let mut bytes = Vec::new();
+    checkpoint.write(&mut bytes).unwrap();
+    assert_eq!(Checkpoint::read(&bytes[..]).unwrap(), checkpoint);
+
+    // Cut off in the middle of the pixels.
+    let err = Checkpoint::read(&bytes[..bytes.len() - 10]).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    let err = Checkpoint::read(&b"P6\n3 2\n255\n"[..]).err().unwrap();
+    assert_eq!(err.to_string(), "not a checkpoint file");
+
+    // With the direct light as well.
+    let mut split = checkpoint.clone();
+    split.framebuffer = Framebuffer::with_direct(3, 2);
+    split.framebuffer.add(1, Color::ones(), 1.0, 1);
+    split
+        .framebuffer
+        .add_direct(1, Color::new(0.5, 0.25, 0.125));
+    let mut bytes = Vec::new();
+    split.write(&mut bytes).unwrap();
+    assert_eq!(Checkpoint::read(&bytes[..]).unwrap(), split);
+}
+
+#[test]
+fn test_resume_matches_straight_render() {
+    use std::sync::Arc;
+
+    use crate::camera::Camera;
+    use crate::materials::lambertian::Lambertian;
+    use crate::render::{ray_color, Background};
+    use crate::sampler::{Sampler, SamplerKind};
+    use crate::schedule::Schedule;
+    use crate::shapes::hittable_list::HittableList;
+    use crate::shapes::sphere::Sphere;
+    use crate::vec3::{Point3, Vec3};
+
+    let (width, height) = (8, 6);
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        Some(Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.2)))),
+    )));
+    let camera = Camera::new(
+        Point3::zero(),
+        Point3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        90.0,
+        width as f64 / height as f64,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    );
+    // Adaptive, so the samples in each pass depend on what was saved.
+    let schedule = Schedule {
+        samples_per_pixel: 24,
+        adaptive: Some(0.02),
+    };
+
+    let sample = |i: i32, j: i32, sampler: &mut dyn Sampler| {
+        let (du, dv) = sampler.get_2d();
+        let u = (i as f64 + du) / (width - 1) as f64;
+        let v = (j as f64 + dv) / (height - 1) as f64;
+        let r = camera.get_ray(u, v, sampler);
+        let c = ray_color(&r, &Background::Sky, &world, None, 10, sampler);
+        (c, Color::zero())
+    };
+    // A pass the way the renderer does it. False once there is nothing left
+    // to do.
+    let render_pass = |framebuffer: &mut Framebuffer, pass: u64| {
+        let samples = schedule.pass(pass, framebuffer);
+        if samples.iter().all(|n| *n == 0) {
+            return false;
+        }
+        schedule.render_pass(framebuffer, &samples, SamplerKind::Sobol, 5, sample);
+        true
+    };
+
+    let mut straight = Framebuffer::new(width, height);
+    let mut pass = 0;
+    while render_pass(&mut straight, pass) {
+        pass += 1;
+    }
+
+    // Stopped after two passes, saved, and carried on from the file.
+    let mut interrupted = Framebuffer::new(width, height);
+    for pass in 0..2 {
+        render_pass(&mut interrupted, pass);
+    }
+    let mut bytes = Vec::new();
+    Checkpoint {
+        args: Vec::new(),
+        seed: 5,
+        pass: 2,
+        framebuffer: interrupted,
+    }
+    .write(&mut bytes)
+    .unwrap();
+    let checkpoint = Checkpoint::read(&bytes[..]).unwrap();
+    let mut resumed = checkpoint.framebuffer;
+    let mut pass = checkpoint.pass;
+    while render_pass(&mut resumed, pass) {
+        pass += 1;
+    }
+
+    assert_eq!(resumed, straight);
+    assert!(straight.samples.iter().any(|n| *n != straight.samples[0]));
+}
@@ -0,0 +1,464 @@
+//! Command line options.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use raytracer::aov::Aov;
+use raytracer::image::{self, exr::Precision};
+use raytracer::sampler::SamplerKind;
+use raytracer::scene::Scene;
+use raytracer::tonemap::Operator;
+use raytracer::vec3::{Point3, Vec3};
+
+pub const USAGE: &str = "\
+usage: raytracer [OPTIONS] [SCENE.toml]
+
+Renders SCENE.toml, or the random scene from the end of the first book when
+no scene is given. Options override what the scene file says.
+
+Output:
+  -o, --output PATH        write the image to PATH, an ASCII ppm goes to
+                           stdout without it. .png and .ppm are tone mapped,
+                           .exr, .pfm and .hdr keep the full range
+      --precision P        half (the default) or float, for .exr
+      --aov LIST           also write these comma separated AOVs when the
+                           render is done: depth, normal, albedo, object-id,
+                           material-id, uv, direct and indirect. They go in
+                           layers of an .exr output, or next to the others
+                           as OUTPUT.depth.png and so on
+
+Image:
+  -w, --width N            image width in pixels
+      --height N           image height in pixels
+      --aspect-ratio R     width / height, used when only one of them is set
+  -s, --samples N          samples per pixel, the most that are taken
+  -d, --max-depth N        maximum number of bounces per ray
+
+Camera:
+      --lookfrom X,Y,Z     camera position
+      --lookat X,Y,Z       point the camera looks at
+      --vup X,Y,Z          up direction
+      --vfov DEGREES       vertical field of view
+      --aperture A         lens aperture, 0 for a pinhole camera
+      --focus-dist D       distance to the plane in focus
+
+Rendering:
+      --time-limit SECONDS stop after about this long
+      --noise-target E     stop once the average standard error of the
+                           pixels, after gamma correction, is below E, e.g.
+                           0.01
+      --adaptive E         adaptive sampling: pixels stop once their standard
+                           error is below E and the samples they didn't take
+                           go to noisier pixels
+      --heatmap PATH       write an image of how many samples each pixel got
+      --write-interval SECONDS
+                           how often the output and checkpoint are written
+                           while rendering, 30 seconds by default
+      --checkpoint PATH    save the render in progress to PATH as well
+      --resume PATH        carry on with the render saved in PATH, with the
+                           same arguments unless they are given again
+      --spectral           trace wavelengths instead of RGB, so dispersive
+                           glass splits light into colors
+      --exposure STOPS     brighten or darken the image, in stops
+      --tonemap NAME       how colors brighter than white are shown: clamp,
+                           reinhard, aces or agx
+      --dither             add a little noise to hide banding in gradients
+      --denoise            remove noise from the output, guided by the
+                           albedo, normals and depth of what the camera sees
+      --sampler NAME       where sample positions come from: independent,
+                           stratified, halton, sobol (the default) or
+                           blue-noise
+  -t, --threads N          number of threads, all cores by default
+      --seed N             seed for the random numbers, the same seed gives
+                           the same image
+
+  -h, --help               print this message
+";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub help: bool,
+    pub scene: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub precision: Option<Precision>,
+    pub aovs: Vec<Aov>,
+
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub aspect_ratio: Option<f64>,
+    pub samples_per_pixel: Option<i32>,
+    pub max_depth: Option<i32>,
+
+    pub lookfrom: Option<Point3>,
+    pub lookat: Option<Point3>,
+    pub vup: Option<Vec3>,
+    pub vfov: Option<f64>,
+    pub aperture: Option<f64>,
+    pub focus_dist: Option<f64>,
+
+    pub time_limit: Option<Duration>,
+    pub noise_target: Option<f64>,
+    pub adaptive: Option<f64>,
+    pub heatmap: Option<PathBuf>,
+    pub write_interval: Option<Duration>,
+    pub checkpoint: Option<PathBuf>,
+    pub resume: Option<PathBuf>,
+    pub spectral: bool,
+    pub exposure: Option<f64>,
+    pub tonemap: Option<Operator>,
+    pub dither: bool,
+    pub denoise: bool,
+    pub sampler: Option<SamplerKind>,
+    pub threads: Option<usize>,
+    pub seed: Option<u64>,
+}
+
+impl Options {
+    /// Parses the arguments, without the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Accept both `--width 100` and `--width=100`.
+            let (flag, inline) = match arg.find('=') {
+                Some(i) if arg.starts_with("--") => (&arg[..i], Some(arg[i + 1..].to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{}: missing value", flag))
+            };
+
+            match flag {
+                "-h" | "--help" => options.help = true,
+                "-o" | "--output" => options.output = Some(PathBuf::from(value()?)),
+                "--precision" => {
+                    let name = value()?;
+                    options.precision = Some(name.parse().map_err(|e| format!("{}: {}", flag, e))?);
+                }
+                "--aov" => {
+                    options.aovs.clear();
+                    for name in value()?.split(',') {
+                        let aov = name
+                            .trim()
+                            .parse()
+                            .map_err(|e| format!("{}: {}", flag, e))?;
+                        if !options.aovs.contains(&aov) {
+                            options.aovs.push(aov);
+                        }
+                    }
+                }
+                "-w" | "--width" => options.width = Some(positive(flag, &value()?)?),
+                "--height" => options.height = Some(positive(flag, &value()?)?),
+                "--aspect-ratio" => {
+                    options.aspect_ratio = Some(positive_f64(flag, &value()?)?);
+                }
+                "-s" | "--samples" => {
+                    options.samples_per_pixel = Some(positive(flag, &value()?)?);
+                }
+                "-d" | "--max-depth" => options.max_depth = Some(positive(flag, &value()?)?),
+                "--lookfrom" => options.lookfrom = Some(vector(flag, &value()?)?),
+                "--lookat" => options.lookat = Some(vector(flag, &value()?)?),
+                "--vup" => options.vup = Some(vector(flag, &value()?)?),
+                "--vfov" => {
+                    let vfov = number(flag, &value()?)?;
+                    if vfov <= 0.0 || vfov >= 180.0 {
+                        return Err(format!("{}: must be between 0 and 180 degrees", flag));
+                    }
+                    options.vfov = Some(vfov);
+                }
+                "--aperture" => {
+                    let aperture = number(flag, &value()?)?;
+                    if aperture < 0.0 {
+                        return Err(format!("{}: must not be negative", flag));
+                    }
+                    options.aperture = Some(aperture);
+                }
+                "--focus-dist" => options.focus_dist = Some(positive_f64(flag, &value()?)?),
+                "--time-limit" => options.time_limit = Some(seconds(flag, &value()?)?),
+                "--noise-target" => {
+                    options.noise_target = Some(positive_f64(flag, &value()?)?);
+                }
+                "--adaptive" => options.adaptive = Some(positive_f64(flag, &value()?)?),
+                "--heatmap" => options.heatmap = Some(PathBuf::from(value()?)),
+                "--write-interval" => {
+                    options.write_interval = Some(seconds(flag, &value()?)?);
+                }
+                "--checkpoint" => options.checkpoint = Some(PathBuf::from(value()?)),
+                "--resume" => options.resume = Some(PathBuf::from(value()?)),
+                "--spectral" => options.spectral = true,
+                "--exposure" => options.exposure = Some(finite(flag, &value()?)?),
+                "--tonemap" => {
+                    let name = value()?;
+                    options.tonemap = Some(name.parse().map_err(|e| format!("{}: {}", flag, e))?);
+                }
+                "--dither" => options.dither = true,
+                "--denoise" => options.denoise = true,
+                "--sampler" => {
+                    let name = value()?;
+                    options.sampler = Some(name.parse().map_err(|e| format!("{}: {}", flag, e))?);
+                }
+                "-t" | "--threads" => options.threads = Some(positive(flag, &value()?)?),
+                "--seed" => options.seed = Some(number(flag, &value()?)?),
+                _ if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+                _ if options.scene.is_none() => options.scene = Some(PathBuf::from(arg)),
+                _ => return Err(format!("unexpected argument `{}`", arg)),
+            }
+        }
+
+        if options.width.is_some() && options.height.is_some() && options.aspect_ratio.is_some() {
+            return Err("--aspect-ratio can't be used with both --width and --height".to_string());
+        }
+
+        // Catch a typo in a file name before rendering rather than after.
+        for (flag, path) in &[
+            ("--output", &options.output),
+            ("--heatmap", &options.heatmap),
+        ] {
+            if let Some(path) = path {
+                image::check_format(path).map_err(|e| format!("{}: {}", flag, e))?;
+            }
+        }
+        // When resuming, the output can come from the saved arguments. These
+        // are checked once they have been added.
+        if options.resume.is_none() {
+            if !options.aovs.is_empty() && options.output.is_none() {
+                return Err("--aov needs --output".to_string());
+            }
+            let is_exr = |path: &Option<PathBuf>| {
+                path.as_ref()
+                    .and_then(|path| path.extension())
+                    .is_some_and(|e| e.eq_ignore_ascii_case("exr"))
+            };
+            if options.precision.is_some() && !is_exr(&options.output) && !is_exr(&options.heatmap)
+            {
+                return Err("--precision only applies to .exr output".to_string());
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Overrides the scene's settings with the ones given on the command line.
+    pub fn apply(&self, scene: &mut Scene) -> Result<(), String> {
+        let settings = &mut scene.settings;
+        let aspect_ratio = self.aspect_ratio.unwrap_or_else(|| settings.aspect_ratio());
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => {
+                settings.image_width = width;
+                settings.image_height = height;
+            }
+            (Some(width), None) => {
+                settings.image_width = width;
+                settings.image_height = ((width as f64 / aspect_ratio) as i32).max(1);
+            }
+            (None, Some(height)) => {
+                settings.image_width = ((height as f64 * aspect_ratio) as i32).max(1);
+                settings.image_height = height;
+            }
+            (None, None) if self.aspect_ratio.is_some() => {
+                settings.image_height =
+                    ((settings.image_width as f64 / aspect_ratio) as i32).max(1);
+            }
+            (None, None) => {}
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            settings.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.max_depth = max_depth;
+        }
+        if self.spectral {
+            settings.spectral = true;
+        }
+        if let Some(exposure) = self.exposure {
+            settings.tone_map.exposure = exposure;
+        }
+        if let Some(operator) = self.tonemap {
+            settings.tone_map.operator = operator;
+        }
+        if self.dither {
+            settings.tone_map.dither = true;
+        }
+
+        let camera = &mut scene.camera;
+        if let Some(lookfrom) = self.lookfrom {
+            camera.lookfrom = lookfrom;
+        }
+        if let Some(lookat) = self.lookat {
+            camera.lookat = lookat;
+        }
+        if let Some(vup) = self.vup {
+            camera.vup = vup;
+        }
+        if let Some(vfov) = self.vfov {
+            camera.vfov = vfov;
+        }
+        if let Some(aperture) = self.aperture {
+            camera.aperture = aperture;
+        }
+        if self.focus_dist.is_some() {
+            camera.focus_dist = self.focus_dist;
+        }
+
+        if camera.lookfrom == camera.lookat {
+            return Err("the camera's lookfrom and lookat must be different".to_string());
+        }
+        if camera
+            .vup
+            .cross(&(camera.lookfrom - camera.lookat))
+            .length_squared()
+            < 1e-12
+        {
+            return Err("the camera's vup must not point along the view direction".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// The arguments for resuming a render started with `saved`: those
+/// followed by the ones given now, without `--resume` itself.
+pub fn resumed_args(saved: &[String], args: &[String]) -> Vec<String> {
+    let mut resumed = saved.to_vec();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--resume" {
+            args.next();
+        } else if !arg.starts_with("--resume=") {
+            resumed.push(arg.clone());
+        }
+    }
+    resumed
+}
+
+fn number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{}: `{}` is not a valid number", flag, value))
+}
+
+fn positive<T: FromStr + Default + PartialOrd>(flag: &str, value: &str) -> Result<T, String> {
+    let n = number(flag, value)?;
+    if n > T::default() {
+        Ok(n)
+    } else {
+        Err(format!("{}: must be greater than zero", flag))
+    }
+}
+
+fn positive_f64(flag: &str, value: &str) -> Result<f64, String> {
+    let n: f64 = positive(flag, value)?;
+    finite(flag, value).map(|_| n)
+}
+
+fn finite(flag: &str, value: &str) -> Result<f64, String> {
+    let n: f64 = number(flag, value)?;
+    if n.is_finite() {
+        Ok(n)
+    } else {
+        Err(format!("{}: must be finite", flag))
+    }
+}
+
+fn seconds(flag: &str, value: &str) -> Result<Duration, String> {
+    positive_f64(flag, value).map(Duration::from_secs_f64)
+}
+
+/// Parses a vector written as `X,Y,Z`.
+fn vector(flag: &str, value: &str) -> Result<Vec3, String> {
+    let parts = value
+        .split(',')
+        .map(|part| number::<f64>(flag, part.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("{}: expected X,Y,Z but got `{}`", flag, value)),
+    }
+}
+
+#[test]
+fn test_parse() {
+    let args = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+
+    let options = Options::parse(args(
+        "scene.toml -w 320 --samples=16 --lookfrom 1,2,3 --vfov 40 --seed 7 --spectral --sampler halton --exposure -1.5 --tonemap aces --denoise --time-limit 90 -o out.exr --precision float --aov depth,albedo,depth",
+    ))
+    .unwrap();
+    assert_eq!(
+        options,
+        Options {
+            scene: Some(PathBuf::from("scene.toml")),
+            output: Some(PathBuf::from("out.exr")),
+            precision: Some(Precision::Float),
+            aovs: vec![Aov::Depth, Aov::Albedo],
+            width: Some(320),
+            samples_per_pixel: Some(16),
+            lookfrom: Some(Point3::new(1.0, 2.0, 3.0)),
+            vfov: Some(40.0),
+            seed: Some(7),
+            spectral: true,
+            sampler: Some(SamplerKind::Halton),
+            exposure: Some(-1.5),
+            tonemap: Some(Operator::Aces),
+            denoise: true,
+            time_limit: Some(Duration::from_secs(90)),
+            ..Options::default()
+        }
+    );
+    assert!(Options::parse(args("--help")).unwrap().help);
+
+    // A single pixel is as small as an image gets.
+    let options = Options::parse(args("-w 4 --height 1")).unwrap();
+    assert_eq!((options.width, options.height), (Some(4), Some(1)));
+
+    let err = |s: &str| Options::parse(args(s)).err().unwrap();
+    assert_eq!(err("-w 0"), "-w: must be greater than zero");
+    assert_eq!(
+        err("--samples lots"),
+        "--samples: `lots` is not a valid number"
+    );
+    assert_eq!(
+        err("--lookat 1,2"),
+        "--lookat: expected X,Y,Z but got `1,2`"
+    );
+    assert_eq!(
+        err("--vfov 180"),
+        "--vfov: must be between 0 and 180 degrees"
+    );
+    assert_eq!(err("--max-depth"), "--max-depth: missing value");
+    assert_eq!(err("--fast"), "unknown option `--fast`");
+    assert_eq!(
+        err("--sampler best"),
+        "--sampler: unknown sampler `best`, use one of independent, stratified, halton, sobol, blue-noise"
+    );
+    assert_eq!(
+        err("--aov depth,speed -o out.png"),
+        "--aov: unknown AOV `speed`, use one of depth, normal, albedo, object-id, material-id, uv, direct, indirect"
+    );
+    assert_eq!(err("--aov depth"), "--aov needs --output");
+    assert!(Options::parse(args("--resume render.ckpt --aov depth")).is_ok());
+    assert_eq!(
+        err("-o image.png --precision float"),
+        "--precision only applies to .exr output"
+    );
+    assert_eq!(
+        err("-o image.jpg"),
+        "--output: don't know how to write image.jpg, use a .png, .ppm, .exr, .pfm or .hdr extension"
+    );
+    assert_eq!(err("a.toml b.toml"), "unexpected argument `b.toml`");
+
+    assert_eq!(
+        resumed_args(
+            &args("scene.toml -s 100"),
+            &args("--resume x.ckpt -s 200 -t 2")
+        ),
+        args("scene.toml -s 100 -s 200 -t 2")
+    );
+}
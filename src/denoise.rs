@@ -0,0 +1,331 @@
+//! Removing the noise left in a render with few samples, with an
+//! edge-avoiding à-trous wavelet filter (Dammertz et al. 2010), weighted like
+//! in SVGF (Schied et al. 2017).
+//!
+//! Every pass blurs with a 5x5 kernel whose taps are twice as far apart as in
+//! the pass before, so a few passes cover a wide area. Neighbours only count
+//! as much as they look like the same surface: a similar normal and depth,
+//! and a brightness within what the noise explains. Textures are kept sharp
+//! by filtering the light arriving at surfaces, the image divided by their
+//! albedo, and multiplying the albedo back in at the end.
+
+use rayon::prelude::*;
+
+use crate::aov::FirstHits;
+use crate::framebuffer::{luminance, Framebuffer};
+use crate::image::Image;
+use crate::vec3::Color;
+
+/// The B3 spline, the filter weights along each axis.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Albedos darker than this aren't divided by, mainly lights, which have
+/// none.
+const MIN_ALBEDO: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denoiser {
+    /// Number of passes. The filter reaches `2^(passes + 1)` pixels out.
+    pub passes: u32,
+    /// How many standard deviations of the noise two brightnesses can be
+    /// apart and still be averaged.
+    pub color_sigma: f64,
+    /// How sharply normals have to agree, as a power of their dot product.
+    pub normal_power: f64,
+    /// How much the depth can change per pixel, relative to the depth.
+    pub depth_sigma: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser {
+            passes: 5,
+            color_sigma: 4.0,
+            normal_power: 128.0,
+            depth_sigma: 0.1,
+        }
+    }
+}
+
+impl Denoiser {
+    /// Denoises `image`, whose pixels' luminance has the given `variance`,
+    /// guided by the albedo, normals and depth in `guide`. Infinite variance,
+    /// for pixels with fewer than two samples, leaves only the guide to go
+    /// on.
+    pub fn denoise(&self, image: &Image, variance: &[f64], guide: &FirstHits) -> Image {
+        let (width, height) = (image.width, image.height);
+        assert_eq!((guide.width, guide.height), (width, height));
+        assert_eq!(variance.len(), width * height);
+
+        let albedo = guide
+            .albedo
+            .iter()
+            .map(|a| {
+                let divisor = |v: f64| if v > MIN_ALBEDO { v } else { 1.0 };
+                Color::new(divisor(a.x), divisor(a.y), divisor(a.z))
+            })
+            .collect::<Vec<_>>();
+        let mut light = image
+            .pixels
+            .iter()
+            .zip(&albedo)
+            .map(|(c, a)| *c / *a)
+            .collect::<Vec<_>>();
+        let mut variance = variance.to_vec();
+
+        for pass in 0..self.passes {
+            let step = 1 << pass;
+            let blurred = blur(&variance, width, height);
+            let (filtered, filtered_variance) = (0..width * height)
+                .into_par_iter()
+                .map(|p| self.filter(p, step, &light, &variance, blurred[p], &albedo, guide))
+                .unzip();
+            light = filtered;
+            variance = filtered_variance;
+        }
+
+        Image {
+            width,
+            height,
+            pixels: light.iter().zip(&albedo).map(|(l, a)| *l * *a).collect(),
+        }
+    }
+
+    /// Denoises the average of the samples in `framebuffer`, using their
+    /// variance.
+    pub fn denoise_framebuffer(&self, framebuffer: &Framebuffer, guide: &FirstHits) -> Image {
+        let variance = (0..framebuffer.sum.len())
+            .map(|index| framebuffer.variance(index))
+            .collect::<Vec<_>>();
+        self.denoise(&framebuffer.image(), &variance, guide)
+    }
+
+    /// The filter at pixel `p`, with taps `step` pixels apart, for a pixel
+    /// whose variance around it is `local_variance`. Returns the light and
+    /// its variance.
+    #[allow(clippy::too_many_arguments)]
+    fn filter(
+        &self,
+        p: usize,
+        step: usize,
+        light: &[Color],
+        variance: &[f64],
+        local_variance: f64,
+        albedo: &[Color],
+        guide: &FirstHits,
+    ) -> (Color, f64) {
+        let (width, height) = (guide.width as isize, guide.height as isize);
+        let (x, y) = ((p % guide.width) as isize, (p / guide.width) as isize);
+        let brightness = |q: usize| luminance(light[q] * albedo[q]);
+        let normal = guide.normal[p].unit();
+        let has_normal = guide.normal[p].length_squared() > 1e-12;
+        let depth = guide.depth[p];
+        // How far apart brightnesses can be is decided by the noise around
+        // this pixel rather than its own, which is too noisy itself: a pixel
+        // whose few samples all missed the light would seem to have none.
+        let color_scale = self.color_sigma * local_variance.sqrt() + 1e-10;
+
+        let mut sum = Color::zero();
+        let mut variance_sum = 0.0;
+        let mut total = 0.0;
+        for (dy, ky) in KERNEL.iter().enumerate() {
+            for (dx, kx) in KERNEL.iter().enumerate() {
+                let qx = x + (dx as isize - 2) * step as isize;
+                let qy = y + (dy as isize - 2) * step as isize;
+                if qx < 0 || qx >= width || qy < 0 || qy >= height {
+                    continue;
+                }
+                let q = (qy * width + qx) as usize;
+
+                let mut w = kx * ky;
+                if q != p {
+                    // Brightness, unless the noise is too unknown to tell.
+                    if color_scale.is_finite() {
+                        w *= (-(brightness(p) - brightness(q)).abs() / color_scale).exp();
+                    }
+                    // Normals, where something was hit.
+                    let has_other = guide.normal[q].length_squared() > 1e-12;
+                    if has_normal && has_other {
+                        w *= normal
+                            .dot(&guide.normal[q].unit())
+                            .max(0.0)
+                            .powf(self.normal_power);
+                    } else if has_normal != has_other {
+                        w = 0.0;
+                    }
+                    // Depth, relative so it works at any scale, and per
+                    // pixel so slanted surfaces aren't cut up.
+                    let other = guide.depth[q];
+                    if depth.is_finite() && other.is_finite() {
+                        let distance = ((qx - x).pow(2) as f64 + (qy - y).pow(2) as f64).sqrt();
+                        let change =
+                            (depth - other).abs() / (depth.max(other).max(1e-10) * distance);
+                        w *= (-change / self.depth_sigma).exp();
+                    } else if depth.is_finite() != other.is_finite() {
+                        w = 0.0;
+                    }
+                }
+
+                // Skipped rather than weighted by zero, which would turn an
+                // infinite variance into NaN.
+                if w == 0.0 {
+                    continue;
+                }
+                sum += w * light[q];
+                variance_sum += w * w * variance[q];
+                total += w;
+            }
+        }
+        (sum / total, variance_sum / (total * total))
+    }
+}
+
+/// `values` blurred with a 3x3 Gaussian.
+fn blur(values: &[f64], width: usize, height: usize) -> Vec<f64> {
+    const WEIGHTS: [f64; 3] = [0.25, 0.5, 0.25];
+    (0..width * height)
+        .map(|p| {
+            let (x, y) = (p % width, p / width);
+            let mut sum = 0.0;
+            let mut total = 0.0;
+            for (dy, wy) in WEIGHTS.iter().enumerate() {
+                for (dx, wx) in WEIGHTS.iter().enumerate() {
+                    let (qx, qy) = ((x + dx).wrapping_sub(1), (y + dy).wrapping_sub(1));
+                    if qx < width && qy < height {
+                        sum += wx * wy * values[qy * width + qx];
+                        total += wx * wy;
+                    }
+                }
+            }
+            sum / total
+        })
+        .collect()
+}
+
+#[test]
+fn test_denoise() {
+    use crate::utils::Rng;
+    use crate::vec3::Vec3;
+
+    // A wall on the left facing the camera, a red floor on the right facing
+    // up, both lit evenly and rendered with a lot of noise.
+    let (width, height) = (32, 16);
+    let left = |p: usize| p % width < width / 2;
+    let red = Color::new(0.8, 0.1, 0.1);
+    let truth = |p: usize| {
+        if left(p) {
+            Color::new(0.5, 0.5, 0.5)
+        } else {
+            red * 2.0
+        }
+    };
+    let mut rng = Rng::new(3);
+    let mut image = Image::new(width, height);
+    let mut variance = vec![0.0; width * height];
+    for (p, pixel) in image.pixels.iter_mut().enumerate() {
+        let noise = 0.5 + rng.random();
+        *pixel = truth(p) * noise;
+        // The variance of a uniform random number is 1/12.
+        variance[p] = luminance(truth(p)).powi(2) / 12.0;
+    }
+    let guide = FirstHits {
+        width,
+        height,
+        depth: (0..width * height)
+            .map(|p| if left(p) { 5.0 } else { 2.0 })
+            .collect(),
+        normal: (0..width * height)
+            .map(|p| {
+                if left(p) {
+                    Vec3::new(0.0, 0.0, 1.0)
+                } else {
+                    Vec3::new(0.0, 1.0, 0.0)
+                }
+            })
+            .collect(),
+        albedo: (0..width * height)
+            .map(|p| {
+                if left(p) {
+                    Color::new(0.5, 0.5, 0.5)
+                } else {
+                    red
+                }
+            })
+            .collect(),
+        object_id: vec![0; width * height],
+        material_id: vec![0; width * height],
+        uv: vec![(0.0, 0.0); width * height],
+    };
+
+    let error = |image: &Image| {
+        let total: f64 = (0..width * height)
+            .map(|p| (image.pixels[p] - truth(p)).length_squared())
+            .sum();
+        (total / (width * height) as f64).sqrt()
+    };
+    let denoised = Denoiser::default().denoise(&image, &variance, &guide);
+    assert!(
+        error(&denoised) < 0.2 * error(&image),
+        "{} {}",
+        error(&denoised),
+        error(&image)
+    );
+    // Nothing leaks across the edge, even right next to it.
+    for y in 0..height {
+        let wall = denoised.pixels[y * width + width / 2 - 1];
+        let floor = denoised.pixels[y * width + width / 2];
+        assert!((wall.x - wall.y).abs() < 0.02, "{:?}", wall);
+        assert!((wall - truth(0)).length() < 0.15, "{:?}", wall);
+        assert!((floor - red * 2.0).length() < 0.2, "{:?}", floor);
+        assert!(floor.y < 0.3 && floor.x > 1.2, "{:?}", floor);
+    }
+}
+
+#[test]
+fn test_denoise_single_samples() {
+    use crate::vec3::Vec3;
+
+    // One sample per pixel leaves no variance to go on, and the two halves
+    // facing different ways don't count for each other at all.
+    let (width, height) = (8, 4);
+    let mut framebuffer = Framebuffer::new(width, height);
+    for p in 0..width * height {
+        let c = Color::new(0.1, 0.2, 0.3) * (1 + p % 3) as f64;
+        framebuffer.add(p, c, luminance(c).powi(2), 1);
+    }
+    let left = |p: usize| p % width < width / 2;
+    let guide = FirstHits {
+        width,
+        height,
+        depth: vec![1.0; width * height],
+        normal: (0..width * height)
+            .map(|p| {
+                if left(p) {
+                    Vec3::new(0.0, 0.0, 1.0)
+                } else {
+                    Vec3::new(1.0, 0.0, 0.0)
+                }
+            })
+            .collect(),
+        albedo: vec![Color::ones(); width * height],
+        object_id: vec![0; width * height],
+        material_id: vec![0; width * height],
+        uv: vec![(0.0, 0.0); width * height],
+    };
+
+    let denoiser = Denoiser::default();
+    let denoised = denoiser.denoise_framebuffer(&framebuffer, &guide);
+    for pixel in &denoised.pixels {
+        assert!(pixel.x.is_finite() && pixel.y.is_finite() && pixel.z.is_finite());
+    }
+
+    // The variance stays infinite rather than becoming NaN, which would
+    // spread to the pixels around it in the next pass.
+    let light = framebuffer.image().pixels;
+    let variance = vec![f64::INFINITY; width * height];
+    let albedo = vec![Color::ones(); width * height];
+    for p in 0..width * height {
+        let (_, v) = denoiser.filter(p, 1, &light, &variance, f64::INFINITY, &albedo, &guide);
+        assert_eq!(v, f64::INFINITY);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::image::{srgb_to_linear, Image};
+use crate::vec3::Color;
+
+/// Sums of the samples taken so far for every pixel, so passes of a few
+/// samples each can be added up into an image that keeps getting better.
+/// Pixels are stored like in `Image`, row by row from the top left.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub sum: Vec<Color>,
+    /// Sum of the squared luminance of the samples, for the noise estimate.
+    pub luminance_sq: Vec<f64>,
+    pub samples: Vec<u32>,
+    /// Sum of the direct light in the samples, when it's kept apart for the
+    /// direct and indirect AOVs.
+    pub direct: Option<Vec<Color>>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            sum: vec![Color::zero(); width * height],
+            luminance_sq: vec![0.0; width * height],
+            samples: vec![0; width * height],
+            direct: None,
+        }
+    }
+
+    /// Like `new`, but keeping track of the direct light too.
+    pub fn with_direct(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            direct: Some(vec![Color::zero(); width * height]),
+            ..Framebuffer::new(width, height)
+        }
+    }
+
+    /// Adds `samples` samples adding up to `sum`, whose squared luminances
+    /// add up to `luminance_sq`, to the pixel at `index`.
+    pub fn add(&mut self, index: usize, sum: Color, luminance_sq: f64, samples: u32) {
+        self.sum[index] += sum;
+        self.luminance_sq[index] += luminance_sq;
+        self.samples[index] += samples;
+    }
+
+    /// Adds direct light adding up to `direct`, from samples that are also
+    /// added with `add`. Does nothing unless made `with_direct`.
+    pub fn add_direct(&mut self, index: usize, direct: Color) {
+        if let Some(sums) = &mut self.direct {
+            sums[index] += direct;
+        }
+    }
+
+    /// The average of the samples so far. Pixels without any are black.
+    pub fn image(&self) -> Image {
+        self.average(&self.sum)
+    }
+
+    /// The average direct and indirect light, if it's kept track of.
+    pub fn direct_and_indirect(&self) -> Option<(Image, Image)> {
+        let direct = self.average(self.direct.as_ref()?);
+        let mut indirect = self.image();
+        for (pixel, d) in indirect.pixels.iter_mut().zip(&direct.pixels) {
+            *pixel -= *d;
+        }
+        Some((direct, indirect))
+    }
+
+    fn average(&self, sums: &[Color]) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            if self.samples[i] > 0 {
+                *pixel = sums[i] / self.samples[i] as f64;
+            }
+        }
+        image
+    }
+
+    /// Variance of the pixel's average luminance, as an estimate of the
+    /// true brightness. Infinite until the pixel has at least two samples.
+    pub fn variance(&self, index: usize) -> f64 {
+        let n = self.samples[index] as f64;
+        if n < 2.0 {
+            return f64::INFINITY;
+        }
+        let mean = luminance(self.sum[index]) / n;
+        let variance = ((self.luminance_sq[index] / n - mean * mean) * n / (n - 1.0)).max(0.0);
+        variance / n
+    }
+
+    /// Standard error of the pixel's brightness as it will be displayed,
+    /// after gamma correction, so 0.01 is about 2.5 steps out of 255.
+    /// Infinite until the pixel has at least two samples.
+    pub fn error(&self, index: usize) -> f64 {
+        let n = self.samples[index] as f64;
+        if n < 2.0 {
+            return f64::INFINITY;
+        }
+        let mean = luminance(self.sum[index]) / n;
+        let standard_error = self.variance(index).sqrt();
+
+        // sRGB is close to gamma 2, the square root, which scales small
+        // errors by 1 / (2 sqrt(L)). Very dark pixels are clamped so a
+        // single stray sample doesn't count as infinitely noisy.
+        standard_error / (2.0 * mean.max(1e-4).sqrt())
+    }
+
+    /// How many samples each pixel got, from black for the fewest through
+    /// blue, red and yellow to white for the most.
+    pub fn heatmap(&self) -> Image {
+        const RAMP: [Color; 5] = [
+            Color::new(0.0, 0.0, 0.0),
+            Color::new(0.1, 0.1, 0.8),
+            Color::new(0.9, 0.1, 0.2),
+            Color::new(1.0, 0.8, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        ];
+        let min = self.samples.iter().copied().min().unwrap_or(0) as f64;
+        let max = self.samples.iter().copied().max().unwrap_or(0) as f64;
+
+        let mut image = Image::new(self.width, self.height);
+        for (pixel, samples) in image.pixels.iter_mut().zip(&self.samples) {
+            let t = if max > min {
+                (*samples as f64 - min) / (max - min) * (RAMP.len() - 1) as f64
+            } else {
+                0.0
+            };
+            let i = (t as usize).min(RAMP.len() - 2);
+            let color = RAMP[i] + (t - i as f64) * (RAMP[i + 1] - RAMP[i]);
+            // The ramp is meant for the screen, images are saved as sRGB.
+            *pixel = Color::new(
+                srgb_to_linear(color.x),
+                srgb_to_linear(color.y),
+                srgb_to_linear(color.z),
+            );
+        }
+        image
+    }
+
+    /// Average `error` over the image.
+    pub fn noise(&self) -> f64 {
+        let total: f64 = (0..self.sum.len()).map(|i| self.error(i)).sum();
+        total / self.sum.len() as f64
+    }
+}
+
+/// Relative luminance of a linear sRGB color.
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+#[test]
+fn test_framebuffer() {
+    let mut framebuffer = Framebuffer::new(2, 1);
+    assert_eq!(framebuffer.noise(), f64::INFINITY);
+
+    // The same color every time has no noise, alternating colors do.
+    let gray = Color::new(0.25, 0.25, 0.25);
+    for i in 0..4 {
+        framebuffer.add(0, gray, luminance(gray).powi(2), 1);
+        let c = if i % 2 == 0 {
+            Color::zero()
+        } else {
+            2.0 * gray
+        };
+        framebuffer.add(1, c, luminance(c).powi(2), 1);
+    }
+    assert_eq!(framebuffer.samples, vec![4, 4]);
+    assert!(framebuffer.error(0) < 1e-9);
+    // Standard deviation 0.289, over sqrt(4) samples, at brightness 0.5.
+    let expected = (1.0f64 / 12.0).sqrt() / 2.0 / (2.0 * 0.5);
+    assert!((framebuffer.error(1) - expected).abs() < 1e-9);
+    assert!((framebuffer.noise() - expected / 2.0).abs() < 1e-9);
+
+    let image = framebuffer.image();
+    assert_eq!(image.pixels, vec![gray, gray]);
+    assert!(framebuffer.direct_and_indirect().is_none());
+
+    framebuffer.add(1, gray, 0.0, 4);
+    let heatmap = framebuffer.heatmap();
+    assert_eq!(heatmap.pixels, vec![Color::zero(), Color::ones()]);
+
+    let mut split = Framebuffer::with_direct(1, 1);
+    split.add(0, 2.0 * gray, 0.0, 2);
+    split.add_direct(0, 0.5 * gray);
+    let (direct, indirect) = split.direct_and_indirect().unwrap();
+    assert_eq!(direct.pixels, vec![0.25 * gray]);
+    assert_eq!(indirect.pixels, vec![0.75 * gray]);
+}
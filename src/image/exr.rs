@@ -0,0 +1,258 @@
+//! OpenEXR, uncompressed scanline images with any number of half or float
+//! channels. Layers are written the usual way, as channels named
+//! `layer.R`, `layer.G` and so on.
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use super::Image;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    /// 16-bit floats, plenty for colors and half the size.
+    Half,
+    Float,
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Precision, String> {
+        match s {
+            "half" => Ok(Precision::Half),
+            "float" => Ok(Precision::Float),
+            _ => Err(format!("unknown precision `{}`, use half or float", s)),
+        }
+    }
+}
+
+/// One channel of an image, stored like the pixels of `Image`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+impl Channel {
+    /// The red, green and blue channels of `image`, called `R`, `G` and `B`
+    /// in `layer` or on their own without one.
+    pub fn rgb(image: &Image, layer: Option<&str>) -> Vec<Channel> {
+        let prefix = layer.map(|l| format!("{}.", l)).unwrap_or_default();
+        let channel = |name: &str, f: fn(&super::Color) -> f64| Channel {
+            name: format!("{}{}", prefix, name),
+            values: image.pixels.iter().map(|c| f(c) as f32).collect(),
+        };
+        vec![
+            channel("R", |c| c.x),
+            channel("G", |c| c.y),
+            channel("B", |c| c.z),
+        ]
+    }
+}
+
+pub fn write_exr<W: Write>(
+    mut w: W,
+    width: usize,
+    height: usize,
+    channels: &[Channel],
+    precision: Precision,
+) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    if width == 0 || height == 0 {
+        return Err(invalid("an EXR image can't be empty".to_string()));
+    }
+    if let Some(c) = channels.iter().find(|c| c.values.len() != width * height) {
+        return Err(invalid(format!(
+            "channel {} has {} values, not {}",
+            c.name,
+            c.values.len(),
+            width * height
+        )));
+    }
+
+    // Readers expect the channels sorted by name, in the header and the
+    // pixels.
+    let mut sorted = channels.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+    let (pixel_type, bytes) = match precision {
+        Precision::Half => (1i32, 2),
+        Precision::Float => (2, 4),
+    };
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+    let mut list = Vec::new();
+    for c in &sorted {
+        list.extend_from_slice(c.name.as_bytes());
+        list.push(0);
+        list.extend_from_slice(&pixel_type.to_le_bytes());
+        // Not perceptually linear, three reserved bytes, no subsampling.
+        list.extend_from_slice(&[0, 0, 0, 0]);
+        list.extend_from_slice(&1i32.to_le_bytes());
+        list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    list.push(0);
+    attribute(&mut header, "channels", "chlist", &list);
+    // No compression.
+    attribute(&mut header, "compression", "compression", &[0]);
+    let mut window = Vec::new();
+    for v in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    // Scanlines from the top.
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+    w.write_all(&header)?;
+
+    // One scanline per chunk, each with its y and size first.
+    let line_size = width * sorted.len() * bytes;
+    let first = header.len() + height * 8;
+    for y in 0..height {
+        let offset = (first + y * (8 + line_size)) as u64;
+        w.write_all(&offset.to_le_bytes())?;
+    }
+    let mut line = Vec::with_capacity(line_size);
+    for y in 0..height {
+        line.clear();
+        for c in &sorted {
+            for v in &c.values[y * width..(y + 1) * width] {
+                match precision {
+                    Precision::Half => line.extend_from_slice(&to_half(*v).to_le_bytes()),
+                    Precision::Float => line.extend_from_slice(&v.to_le_bytes()),
+                }
+            }
+        }
+        w.write_all(&(y as i32).to_le_bytes())?;
+        w.write_all(&(line_size as i32).to_le_bytes())?;
+        w.write_all(&line)?;
+    }
+    w.flush()
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// The nearest 16-bit float, ties to even. Too large becomes infinity.
+pub fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity stays infinity, NaN stays NaN.
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let e = exponent - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    let (half, rest, halfway) = if e <= 0 {
+        // Too small for a normal half, shift the mantissa with its implicit
+        // one into a subnormal.
+        if e < -10 {
+            return sign;
+        }
+        let m = mantissa | 0x80_0000;
+        let shift = (14 - e) as u32;
+        (m >> shift, m & ((1 << shift) - 1), 1 << (shift - 1))
+    } else {
+        (
+            ((e as u32) << 10) | (mantissa >> 13),
+            mantissa & 0x1fff,
+            0x1000,
+        )
+    };
+    // Rounding up can carry into the exponent, which is still right.
+    let round = rest > halfway || (rest == halfway && half & 1 == 1);
+    sign | (half + round as u32) as u16
+}
+
+#[test]
+fn test_to_half() {
+    assert_eq!(to_half(0.0), 0);
+    assert_eq!(to_half(-0.0), 0x8000);
+    assert_eq!(to_half(1.0), 0x3c00);
+    assert_eq!(to_half(-2.0), 0xc000);
+    assert_eq!(to_half(0.5), 0x3800);
+    assert_eq!(to_half(65504.0), 0x7bff);
+    assert_eq!(to_half(1e6), 0x7c00);
+    assert_eq!(to_half(f32::INFINITY), 0x7c00);
+    assert!(to_half(f32::NAN) & 0x3ff != 0);
+    // The smallest subnormal, and half of it rounding to even.
+    assert_eq!(to_half(5.960_464_5e-8), 1);
+    assert_eq!(to_half(2.980_232_2e-8), 0);
+    // 1 + 2^-11 is halfway between 1 and the next half, and goes to 1.
+    assert_eq!(to_half(1.000_488_3), 0x3c00);
+    assert_eq!(to_half(1.000_488_4), 0x3c01);
+}
+
+#[test]
+fn test_write_exr() {
+    use std::convert::TryInto;
+
+    let mut image = Image::new(2, 3);
+    image.set(1, 2, super::Color::new(1.0, 2.0, 3.0));
+    let mut channels = Channel::rgb(&image, None);
+    channels.push(Channel {
+        name: "depth.Z".to_string(),
+        values: vec![0.5; 6],
+    });
+
+    let mut bytes = Vec::new();
+    write_exr(&mut bytes, 2, 3, &channels, Precision::Float).unwrap();
+    assert_eq!(&bytes[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+    // The channels are listed in order, `B` first.
+    let b = bytes.windows(2).position(|w| w == b"B\0").unwrap();
+    let z = bytes.windows(8).position(|w| w == b"depth.Z\0").unwrap();
+    assert!(b < z);
+
+    // The offsets point at scanlines, each holding B, G, R and depth.Z.
+    let header = bytes.len() - 3 * 8 - 3 * (8 + 2 * 4 * 4);
+    let offset = |y: usize| {
+        let at = header + y * 8;
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize
+    };
+    let last = offset(2);
+    assert_eq!(offset(0), header + 3 * 8);
+    assert_eq!(last, bytes.len() - (8 + 32));
+    assert_eq!(bytes[last..last + 4], 2i32.to_le_bytes());
+    let value = |i: usize| {
+        let at = last + 8 + i * 4;
+        f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    };
+    assert_eq!(
+        (0..8).map(value).collect::<Vec<_>>(),
+        vec![0.0, 3.0, 0.0, 2.0, 0.0, 1.0, 0.5, 0.5]
+    );
+
+    let mut half = Vec::new();
+    write_exr(&mut half, 2, 3, &channels, Precision::Half).unwrap();
+    assert_eq!(half.len(), bytes.len() - 3 * 2 * 4 * 2);
+
+    let err = write_exr(Vec::new(), 3, 3, &channels, Precision::Half).err();
+    assert_eq!(err.unwrap().kind(), io::ErrorKind::InvalidInput);
+}
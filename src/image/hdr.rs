@@ -0,0 +1,55 @@
+use std::io::{self, Write};
+
+use super::Image;
+use crate::vec3::Color;
+
+/// Radiance RGBE, with uncompressed scanlines. Negative values are written
+/// as zero.
+pub fn write_hdr<W: Write>(mut w: W, image: &Image) -> io::Result<()> {
+    write!(
+        w,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height, image.width
+    )?;
+    for pixel in &image.pixels {
+        w.write_all(&to_rgbe(*pixel))?;
+    }
+    w.flush()
+}
+
+/// A shared exponent for the brightest channel and 8-bit mantissas.
+fn to_rgbe(c: Color) -> [u8; 4] {
+    let (r, g, b) = (c.x.max(0.0), c.y.max(0.0), c.z.max(0.0));
+    let v = r.max(g).max(b);
+    if v < 1e-32 || !v.is_finite() {
+        return [0; 4];
+    }
+    // v = m * 2^e with m in [0.5, 1).
+    let mut e = v.log2().floor() as i32 + 1;
+    if v / 2f64.powi(e) >= 1.0 {
+        e += 1;
+    }
+    let scale = 256.0 / 2f64.powi(e);
+    [
+        (r * scale) as u8,
+        (g * scale) as u8,
+        (b * scale) as u8,
+        (e + 128) as u8,
+    ]
+}
+
+#[test]
+fn test_write_hdr() {
+    assert_eq!(to_rgbe(Color::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+    assert_eq!(to_rgbe(Color::new(0.0, 6.0, -1.0)), [0, 192, 0, 131]);
+    assert_eq!(to_rgbe(Color::zero()), [0; 4]);
+
+    let mut image = Image::new(2, 1);
+    image.set(1, 0, Color::new(1000.0, 1000.0, 1000.0));
+    let mut hdr = Vec::new();
+    write_hdr(&mut hdr, &image).unwrap();
+    let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n";
+    assert_eq!(&hdr[..header.len()], header);
+    // 1000 is 0.977 * 2^10.
+    assert_eq!(&hdr[header.len()..], &[0, 0, 0, 0, 250, 250, 250, 138]);
+}
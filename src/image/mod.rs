@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use crate::utils;
+use crate::vec3::Color;
+
+pub mod exr;
+pub mod hdr;
+pub mod pfm;
+pub mod png;
+pub mod ppm;
+
+/// A rendered image. Pixels are stored in linear color, row by row starting
+/// at the top left corner.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::zero(); width * height],
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    #[inline]
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Encodes as sRGB and rounds to 8 bits per channel, returning tightly
+    /// packed RGB triples. Values outside 0 to 1 are clamped, see
+    /// `ToneMap` for doing better.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 3);
+        for pixel in &self.pixels {
+            for c in &[pixel.x, pixel.y, pixel.z] {
+                bytes.push((255.0 * linear_to_srgb(utils::clamp(*c, 0.0, 1.0)) + 0.5) as u8);
+            }
+        }
+        bytes
+    }
+
+    /// Reads a PNG file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        match extension(path).as_deref() {
+            Some("png") => png::read_png(BufReader::new(file)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "don't know how to read {}, only .png is supported",
+                    path.display()
+                ),
+            )),
+        }
+    }
+
+    /// Writes the image to `path`, picking the format from the extension.
+    /// `.png` and `.ppm` (binary P6) are supported, and the float formats
+    /// `.exr`, `.pfm` and `.hdr`, which keep values above 1. `precision` is
+    /// only used for `.exr`.
+    pub fn save<P: AsRef<Path>>(&self, path: P, precision: exr::Precision) -> io::Result<()> {
+        let path = path.as_ref();
+        let writer = |path: &Path| File::create(path).map(BufWriter::new);
+        match extension(path).as_deref() {
+            Some("png") => png::write_png(writer(path)?, self),
+            Some("ppm") => ppm::write_p6(writer(path)?, self),
+            Some("exr") => self.save_exr(path, precision),
+            Some("pfm") => pfm::write_pfm(writer(path)?, self),
+            Some("hdr") => hdr::write_hdr(writer(path)?, self),
+            _ => Err(unknown_format(path)),
+        }
+    }
+
+    /// Writes an OpenEXR file with `R`, `G` and `B` channels.
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P, precision: exr::Precision) -> io::Result<()> {
+        let w = BufWriter::new(File::create(path)?);
+        let channels = exr::Channel::rgb(self, None);
+        exr::write_exr(w, self.width, self.height, &channels, precision)
+    }
+}
+
+/// The sRGB transfer function, from linear light to the encoded value.
+pub fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The inverse of `linear_to_srgb`.
+pub fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Fails like `Image::save` does for a format it can't write, without
+/// writing anything, so a wrong extension can be caught before rendering.
+pub fn check_format<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    match extension(path).as_deref() {
+        Some("png") | Some("ppm") | Some("exr") | Some("pfm") | Some("hdr") => Ok(()),
+        _ => Err(unknown_format(path)),
+    }
+}
+
+fn unknown_format(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "don't know how to write {}, use a .png, .ppm, .exr, .pfm or .hdr extension",
+            path.display()
+        ),
+    )
+}
+
+/// Whether `path` is for one of the float formats, which should get the
+/// image before tone mapping.
+pub fn is_hdr<P: AsRef<Path>>(path: P) -> bool {
+    matches!(
+        extension(path.as_ref()).as_deref(),
+        Some("exr") | Some("pfm") | Some("hdr")
+    )
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+#[test]
+fn test_image_to_rgb8() {
+    let mut image = Image::new(2, 1);
+    image.set(0, 0, Color::new(0.0, 0.25, 1.0));
+    image.set(1, 0, Color::new(4.0, -1.0, 0.01));
+
+    assert_eq!(image.get(1, 0), Color::new(4.0, -1.0, 0.01));
+    assert_eq!(image.to_rgb8(), vec![0, 137, 255, 255, 0, 25]);
+
+    for i in 0..=255 {
+        let c = i as f64 / 255.0;
+        assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-12);
+    }
+    assert!((linear_to_srgb(0.18) - 0.4613).abs() < 1e-4);
+}
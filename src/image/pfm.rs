@@ -0,0 +1,35 @@
+use std::io::{self, Write};
+
+use super::Image;
+
+/// Portable float map: 32-bit floats, little endian, with the rows from the
+/// bottom up.
+pub fn write_pfm<W: Write>(mut w: W, image: &Image) -> io::Result<()> {
+    // A negative scale means little endian.
+    write!(w, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+    for y in (0..image.height).rev() {
+        for x in 0..image.width {
+            let c = image.get(x, y);
+            for v in &[c.x, c.y, c.z] {
+                w.write_all(&(*v as f32).to_le_bytes())?;
+            }
+        }
+    }
+    w.flush()
+}
+
+#[test]
+fn test_write_pfm() {
+    use crate::vec3::Color;
+
+    let mut image = Image::new(1, 2);
+    image.set(0, 0, Color::new(4.0, 0.5, -1.0));
+
+    let mut pfm = Vec::new();
+    write_pfm(&mut pfm, &image).unwrap();
+    let mut expected = b"PF\n1 2\n-1.0\n".to_vec();
+    for v in &[0.0f32, 0.0, 0.0, 4.0, 0.5, -1.0] {
+        expected.extend_from_slice(&v.to_le_bytes());
+    }
+    assert_eq!(pfm, expected);
+}
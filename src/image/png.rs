@@ -0,0 +1,66 @@
+use std::io::{self, Read, Write};
+
+use super::{srgb_to_linear, Image};
+use crate::vec3::Color;
+
+pub fn write_png<W: Write>(mut w: W, image: &Image) -> io::Result<()> {
+    let mut encoder = ::png::Encoder::new(&mut w, image.width as u32, image.height as u32);
+    encoder.set_color(::png::ColorType::Rgb);
+    encoder.set_depth(::png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.to_rgb8())?;
+    writer.finish()?;
+
+    w.flush()
+}
+
+/// Reads an 8 or 16 bit PNG of any color type. The values are decoded as
+/// sRGB, the inverse of what `Image::to_rgb8` does.
+pub fn read_png<R: Read>(r: R) -> io::Result<Image> {
+    let mut decoder = ::png::Decoder::new(r);
+    decoder.set_transformations(::png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(decoding_error)?;
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(decoding_error)?;
+    let bytes = &buf[..info.buffer_size()];
+
+    let channels = info.color_type.samples();
+    let mut image = Image::new(info.width as usize, info.height as usize);
+    for (pixel, data) in image.pixels.iter_mut().zip(bytes.chunks(channels)) {
+        let linear = |b: u8| srgb_to_linear(b as f64 / 255.0);
+        *pixel = match info.color_type {
+            ::png::ColorType::Grayscale | ::png::ColorType::GrayscaleAlpha => {
+                let c = linear(data[0]);
+                Color::new(c, c, c)
+            }
+            _ => Color::new(linear(data[0]), linear(data[1]), linear(data[2])),
+        };
+    }
+
+    Ok(image)
+}
+
+fn decoding_error(e: ::png::DecodingError) -> io::Error {
+    match e {
+        ::png::DecodingError::IoError(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+#[test]
+fn test_png_round_trip() {
+    let mut image = Image::new(3, 2);
+    image.set(0, 0, Color::new(1.0, 0.0, 0.0));
+    image.set(1, 0, Color::new(0.0, 0.25, 0.0));
+    image.set(2, 1, Color::new(0.0, 0.0, 1.0));
+
+    let mut bytes = Vec::new();
+    write_png(&mut bytes, &image).unwrap();
+    let decoded = read_png(&bytes[..]).unwrap();
+
+    assert_eq!(decoded.width, 3);
+    assert_eq!(decoded.height, 2);
+    assert_eq!(decoded.to_rgb8(), image.to_rgb8());
+}
@@ -0,0 +1,36 @@
+use std::io::{self, Write};
+
+use super::Image;
+
+/// Binary PPM, the same 8-bit gamma corrected values as the ASCII flavor but
+/// a fraction of the size.
+pub fn write_p6<W: Write>(mut w: W, image: &Image) -> io::Result<()> {
+    write!(w, "P6\n{} {}\n255\n", image.width, image.height)?;
+    w.write_all(&image.to_rgb8())?;
+    w.flush()
+}
+
+/// ASCII PPM, one pixel per line.
+pub fn write_p3<W: Write>(mut w: W, image: &Image) -> io::Result<()> {
+    writeln!(w, "P3\n{} {}\n255", image.width, image.height)?;
+    for rgb in image.to_rgb8().chunks(3) {
+        writeln!(w, "{} {} {}", rgb[0], rgb[1], rgb[2])?;
+    }
+    w.flush()
+}
+
+#[test]
+fn test_write_ppm() {
+    use crate::vec3::Color;
+
+    let mut image = Image::new(2, 1);
+    image.set(1, 0, Color::ones());
+
+    let mut p6 = Vec::new();
+    write_p6(&mut p6, &image).unwrap();
+    assert_eq!(p6, b"P6\n2 1\n255\n\x00\x00\x00\xff\xff\xff".to_vec());
+
+    let mut p3 = Vec::new();
+    write_p3(&mut p3, &image).unwrap();
+    assert_eq!(String::from_utf8(p3).unwrap(), "P3\n2 1\n255\n0 0 0\n255 255 255\n");
+}
@@ -1,7 +1,22 @@
+pub mod aov;
 pub mod camera;
+pub mod checkpoint;
+pub mod denoise;
+pub mod framebuffer;
+pub mod image;
 pub mod materials;
+pub mod obj;
+pub mod onb;
+pub mod pdf;
 pub mod ray;
 pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod schedule;
 pub mod shapes;
+pub mod spectrum;
+pub mod textures;
+pub mod tonemap;
+pub mod transform;
 pub mod utils;
 pub mod vec3;
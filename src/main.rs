@@ -1,30 +1,37 @@
-use rayon::prelude::*;
+mod cli;
 
-use std::io::Write;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-pub mod camera;
-pub mod materials;
-pub mod ray;
-pub mod render;
-pub mod shapes;
-pub mod utils;
-pub mod vec3;
-
-use camera::Camera;
-use ray::Ray;
-use render::ray_color;
-use shapes::hittable::{HitRecord, Hittable};
-use shapes::hittable_list::HittableList;
-use shapes::sphere::Sphere;
-use vec3::{Color, Point3, Vec3};
-
-fn random_scene() -> HittableList {
-    use utils::{random, random_range};
+use raytracer::aov::{Aov, FirstHits};
+use raytracer::checkpoint::Checkpoint;
+use raytracer::denoise::Denoiser;
+use raytracer::framebuffer::Framebuffer;
+use raytracer::image::exr::{self, Channel, Precision};
+use raytracer::image::{self, ppm, Image};
+use raytracer::materials;
+use raytracer::render::{ray_color, ray_color_split, ray_spectrum, ray_spectrum_split, Background};
+use raytracer::sampler::{Sampler, SamplerKind};
+use raytracer::scene::{CameraSettings, RenderSettings, Scene};
+use raytracer::schedule::Schedule;
+use raytracer::shapes::bvh::BvhNode;
+use raytracer::shapes::hittable::Hittable;
+use raytracer::shapes::hittable_list::HittableList;
+use raytracer::shapes::sphere::Sphere;
+use raytracer::shapes::tagged::Tagged;
+use raytracer::spectrum::SampledWavelengths;
+use raytracer::tonemap::ToneMap;
+use raytracer::utils::Rng;
+use raytracer::vec3::{Color, Point3, Vec3};
+
+use cli::Options;
+
+fn random_scene(rng: &mut Rng) -> HittableList {
     let mut world = HittableList::new();
-    let material_ground = materials::lambertian::Lambertian {
-        albedo: Color::new(0.5, 0.5, 0.5),
-    };
+    let material_ground = materials::lambertian::Lambertian::new(Color::new(0.5, 0.5, 0.5));
 
     world.add(Box::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
@@ -37,20 +44,20 @@ fn random_scene() -> HittableList {
             let af = a as f64;
             let bf = b as f64;
 
-            let choose_mat = random();
-            let center = Point3::new(af + 0.9 * random(), 0.2, bf + 0.9 * random());
+            let choose_mat = rng.random();
+            let center = Point3::new(af + 0.9 * rng.random(), 0.2, bf + 0.9 * rng.random());
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 let sphere_material: Option<Arc<dyn materials::Material + Sync + Send>> =
                     if choose_mat < 0.8 {
                         // Diffuse
-                        let albedo = Color::random() * Color::random();
-                        Some(Arc::new(materials::lambertian::Lambertian { albedo }))
+                        let albedo = Color::random(rng) * Color::random(rng);
+                        Some(Arc::new(materials::lambertian::Lambertian::new(albedo)))
                     } else if choose_mat < 0.95 {
                         // Metal
-                        let albedo = Color::random_range(0.5, 1.0);
-                        let fuzz = random_range(0.0, 0.5);
+                        let albedo = Color::random_range(rng, 0.5, 1.0);
+                        let fuzz = rng.random_range(0.0, 0.5);
 
-                        Some(Arc::new(materials::metal::Metal { albedo, fuzz }))
+                        Some(Arc::new(materials::metal::Metal::new(albedo, fuzz)))
                     } else {
                         // Glass
                         Some(Arc::new(materials::dielectric::Dielectric { ref_idx: 1.5 }))
@@ -68,19 +75,14 @@ fn random_scene() -> HittableList {
         Some(Arc::new(material1)),
     )));
 
-    let material2 = materials::lambertian::Lambertian {
-        albedo: Color::new(0.4, 0.2, 0.1),
-    };
+    let material2 = materials::lambertian::Lambertian::new(Color::new(0.4, 0.2, 0.1));
     world.add(Box::new(Sphere::new(
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
         Some(Arc::new(material2)),
     )));
 
-    let material3 = materials::metal::Metal {
-        albedo: Color::new(0.7, 0.6, 0.5),
-        fuzz: 0.0,
-    };
+    let material3 = materials::metal::Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
 
     world.add(Box::new(Sphere::new(
         Point3::new(4.0, 1.0, 0.0),
@@ -91,69 +93,480 @@ fn random_scene() -> HittableList {
     world
 }
 
-fn write_color(pixel: &Color, samples_per_pixel: i32) {
-    let scale = 1.0 / samples_per_pixel as f64;
+/// What gets rendered when no scene file is given.
+fn default_scene(seed: u64) -> Scene {
+    const ASPECT_RATIO: f64 = 16.0 / 9.0;
+
+    // Image
+    let image_width = 2560;
+    let settings = RenderSettings {
+        image_width,
+        image_height: (image_width as f64 / ASPECT_RATIO) as i32,
+        samples_per_pixel: 500,
+        max_depth: 50,
+        background: Background::Sky,
+        spectral: false,
+        tone_map: ToneMap::default(),
+    };
+
+    // Camera
+    let camera = CameraSettings {
+        lookfrom: Point3::new(13.0, 2.0, 3.0),
+        lookat: Point3::new(0.0, 0.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 20.0,
+        aperture: 0.1,
+        focus_dist: Some(10.0),
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    };
+
+    Scene {
+        camera,
+        world: random_scene(&mut Rng::new(seed)),
+        lights: HittableList::new(),
+        settings,
+    }
+}
+
+/// How often the image is written while rendering, when there is an output
+/// file.
+const DEFAULT_WRITE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Writes `image` to `path`, or as an ASCII ppm to stdout without one.
+/// Exits if that fails.
+fn save(image: &Image, path: Option<&Path>, precision: Precision) {
+    let result = match path {
+        Some(path) => image.save(path, precision),
+        None => ppm::write_p3(BufWriter::new(std::io::stdout().lock()), image),
+    };
+    if let Err(e) = result {
+        match path {
+            Some(path) => eprintln!("\n{}: {}", path.display(), e),
+            None => eprintln!("\n{}", e),
+        }
+        std::process::exit(1);
+    }
+}
+
+/// The framebuffer and pass to render, either new or carried on from
+/// `checkpoint`. `split` is whether the direct light is needed.
+fn start_framebuffer(
+    checkpoint: Option<Checkpoint>,
+    width: usize,
+    height: usize,
+    split: bool,
+) -> Result<(Framebuffer, u64), String> {
+    match checkpoint {
+        Some(checkpoint) => {
+            let fb = &checkpoint.framebuffer;
+            if (fb.width, fb.height) != (width, height) {
+                return Err(format!(
+                    "the checkpoint is for a {}x{} image, not {}x{}",
+                    fb.width, fb.height, width, height
+                ));
+            }
+            if split && fb.direct.is_none() {
+                return Err("the checkpoint doesn't keep direct light apart, which \
+                     --aov direct and indirect need"
+                    .to_string());
+            }
+            Ok((checkpoint.framebuffer, checkpoint.pass))
+        }
+        None if split => Ok((Framebuffer::with_direct(width, height), 0)),
+        None => Ok((Framebuffer::new(width, height), 0)),
+    }
+}
+
+/// Writes an OpenEXR file with the render and the AOVs in layers, or
+/// `display` with the AOVs in files of their own next to it,
+/// `image.depth.png` for `image.png`, made viewable for 8-bit formats.
+fn save_aovs(
+    path: &Path,
+    render: &Image,
+    display: &Image,
+    aovs: &[(Aov, Image)],
+    tone_map: &ToneMap,
+    precision: Precision,
+) {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    if extension.as_deref() == Some("exr") {
+        let mut channels = Channel::rgb(render, None);
+        for (aov, image) in aovs {
+            channels.extend(aov.channels(image));
+        }
+        let result = File::create(path).and_then(|file| {
+            exr::write_exr(
+                BufWriter::new(file),
+                render.width,
+                render.height,
+                &channels,
+                precision,
+            )
+        });
+        if let Err(e) = result {
+            eprintln!("\n{}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    // Divide color by the number of samples and gamma correct for gamma=2.0.
-    let r = (pixel.x * scale).sqrt();
-    let g = (pixel.y * scale).sqrt();
-    let b = (pixel.z * scale).sqrt();
+    let hdr = image::is_hdr(path);
+    save(display, Some(path), precision);
+    for (aov, image) in aovs {
+        let aov_path = aov_path(path, *aov);
+        let image = match aov {
+            _ if hdr => image.clone(),
+            Aov::Direct | Aov::Indirect => tone_map.apply(image),
+            _ => aov.display(image),
+        };
+        save(&image, Some(&aov_path), precision);
+    }
+}
 
-    let ir = (256.0 * utils::clamp(r, 0.0, 0.999)) as i32;
-    let ig = (256.0 * utils::clamp(g, 0.0, 0.999)) as i32;
-    let ib = (256.0 * utils::clamp(b, 0.0, 0.999)) as i32;
+/// `image.depth.png` for `image.png`.
+fn aov_path(path: &Path, aov: Aov) -> PathBuf {
+    match path.extension() {
+        Some(extension) => path.with_extension(format!("{}.{}", aov, extension.to_string_lossy())),
+        None => path.with_extension(aov.name()),
+    }
+}
 
-    println!("{} {} {}", ir, ig, ib);
+fn parse_options(args: &[String]) -> Options {
+    match Options::parse(args.iter().cloned()) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\nrun `raytracer --help` for usage", e);
+            std::process::exit(2);
+        }
+    }
 }
 
 fn main() {
-    const ASPECT_RATIO: f64 = 16.0 / 9.0;
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut options = parse_options(&args);
+    if options.help {
+        print!("{}", cli::USAGE);
+        return;
+    }
 
-    // Image
-    let image_width = 2560;
-    let image_height = (image_width as f64 / ASPECT_RATIO) as i32;
-    let samples_per_pixel = 500;
-    let max_depth = 50;
+    // When resuming, checkpoints go to the file resumed from, unless
+    // `--checkpoint` is given again now. Not the one the render was started
+    // with, the file may have been moved since.
+    let checkpoint_path = options
+        .checkpoint
+        .clone()
+        .or_else(|| options.resume.clone());
 
-    // World
-    let world = random_scene();
+    // Carry on with the arguments the render was started with. Ones given
+    // now come later and win, except for the seed: samples from another one
+    // can't be added to the ones saved.
+    let checkpoint = options.resume.clone().map(|path| {
+        let checkpoint = Checkpoint::load(&path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+        if let Some(seed) = options.seed.filter(|seed| *seed != checkpoint.seed) {
+            eprintln!(
+                "error: --seed {}: {} was rendered with seed {}",
+                seed,
+                path.display(),
+                checkpoint.seed
+            );
+            std::process::exit(2);
+        }
+        args = cli::resumed_args(&checkpoint.args, &args);
+        options = parse_options(&args);
+        checkpoint
+    });
 
-    // Camera
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let lookat = Point3::new(0.0, 0.0, 0.0);
-    let dist_to_focus = 10.0;
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        Vec3::new(0.0, 1.0, 0.0),
-        20.0,
-        ASPECT_RATIO,
-        0.1,
-        dist_to_focus,
-    );
+    if let Some(threads) = options.threads {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+        {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    }
+
+    // Without a seed every run is different, but the image still only
+    // depends on the seed picked here.
+    let seed = match &checkpoint {
+        Some(checkpoint) => checkpoint.seed,
+        None => options.seed.unwrap_or_else(rand::random),
+    };
+
+    let mut scene = match &options.scene {
+        Some(path) => match Scene::load(path) {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => default_scene(seed),
+    };
+    if let Err(e) = options.apply(&mut scene) {
+        eprintln!("error: {}", e);
+        std::process::exit(2);
+    }
+    let output = options.output.clone();
+    let aovs = options.aovs.clone();
+    let precision = options.precision.unwrap_or(Precision::Half);
+
+    let RenderSettings {
+        image_width,
+        image_height,
+        samples_per_pixel,
+        max_depth,
+        background,
+        spectral,
+        tone_map,
+    } = scene.settings;
+    let cam = scene.camera.camera(scene.settings.aspect_ratio());
+
+    // World, with the objects numbered for their IDs.
+    let world = if aovs.contains(&Aov::ObjectId) {
+        Tagged::tag_all(scene.world)
+    } else {
+        scene.world
+    };
+    let world = BvhNode::new(world, cam.time0, cam.time1);
+
+    let lights = if scene.lights.objects.is_empty() {
+        None
+    } else {
+        Some(&scene.lights)
+    };
+
+    let width = image_width as usize;
+    let height = image_height as usize;
+    let wants_split = aovs.contains(&Aov::Direct) || aovs.contains(&Aov::Indirect);
+    let (mut framebuffer, mut pass) = start_framebuffer(checkpoint, width, height, wants_split)
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        });
+    // A checkpoint that keeps direct light apart goes on doing so even when
+    // it isn't asked for now, or its sums would stop matching.
+    let split = framebuffer.direct.is_some();
+
+    // Images a single pixel wide or tall get the whole view in that pixel.
+    let camera_ray = |i: i32, j: i32, sampler: &mut dyn Sampler| {
+        let (du, dv) = sampler.get_2d();
+        let u = (i as f64 + du) / (image_width - 1).max(1) as f64;
+        let v = (j as f64 + dv) / (image_height - 1).max(1) as f64;
+        cam.get_ray(u, v, sampler)
+    };
+
+    // The color of a sample, and the direct light in it when that's needed.
+    let sample = |i: i32, j: i32, sampler: &mut dyn Sampler| {
+        let r = camera_ray(i, j, sampler);
+        let lights = lights.map(|l| l as &dyn Hittable);
+        match (spectral, split) {
+            (true, false) => {
+                let wavelengths = SampledWavelengths::sample(sampler.get_1d());
+                let s = ray_spectrum(
+                    &r,
+                    &wavelengths,
+                    &background,
+                    &world,
+                    lights,
+                    max_depth,
+                    sampler,
+                );
+                (wavelengths.to_rgb(&s), Color::zero())
+            }
+            (true, true) => {
+                let wavelengths = SampledWavelengths::sample(sampler.get_1d());
+                let (direct, indirect) = ray_spectrum_split(
+                    &r,
+                    &wavelengths,
+                    &background,
+                    &world,
+                    lights,
+                    max_depth,
+                    sampler,
+                );
+                let direct = wavelengths.to_rgb(&direct);
+                (direct + wavelengths.to_rgb(&indirect), direct)
+            }
+            (false, false) => {
+                let color = ray_color(&r, &background, &world, lights, max_depth, sampler);
+                (color, Color::zero())
+            }
+            (false, true) => {
+                let (direct, indirect) =
+                    ray_color_split(&r, &background, &world, lights, max_depth, sampler);
+                (direct + indirect, direct)
+            }
+        }
+    };
+
+    // Render in passes until one of the limits is reached.
+    let schedule = Schedule {
+        samples_per_pixel: samples_per_pixel as u32,
+        adaptive: options.adaptive,
+    };
+    let sampler_kind = options.sampler.unwrap_or(SamplerKind::Sobol);
+    let write_interval = options.write_interval.unwrap_or(DEFAULT_WRITE_INTERVAL);
+    let save_checkpoint = |framebuffer: &Framebuffer, pass: u64| {
+        if let Some(path) = &checkpoint_path {
+            let checkpoint = Checkpoint {
+                args: args.clone(),
+                seed,
+                pass,
+                framebuffer: framebuffer.clone(),
+            };
+            if let Err(e) = checkpoint.save(path) {
+                eprintln!("\n{}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    };
+    // What the camera sees first, for the AOVs and to guide the denoiser.
+    // It doesn't change as samples are added, so it's traced once.
+    let first_hits = (options.denoise
+        || aovs
+            .iter()
+            .any(|aov| !matches!(aov, Aov::Direct | Aov::Indirect)))
+    .then(|| {
+        FirstHits::render(
+            width,
+            height,
+            sampler_kind,
+            seed,
+            &background,
+            &world,
+            |x, y, sampler| camera_ray(x as i32, image_height - 1 - y as i32, sampler),
+        )
+    });
+    let denoiser = options.denoise.then(Denoiser::default);
+    // The average of the samples, denoised if asked for.
+    let render = |framebuffer: &Framebuffer| match (&denoiser, &first_hits) {
+        (Some(denoiser), Some(first_hits)) => denoiser.denoise_framebuffer(framebuffer, first_hits),
+        _ => framebuffer.image(),
+    };
+    // Float formats get the render as it is, the others what should be
+    // displayed.
+    let display = |image: Image| {
+        if output.as_deref().is_some_and(image::is_hdr) {
+            image
+        } else {
+            tone_map.apply(&image)
+        }
+    };
+    let start = Instant::now();
+    let mut last_write = start;
+    let mut time_per_sample = None;
+    loop {
+        let samples = schedule.pass(pass, &framebuffer);
+        let total = samples.iter().map(|n| *n as u64).sum::<u64>();
+        if total == 0 {
+            break;
+        }
+
+        // Stop before a pass that would go over the time limit.
+        if let (Some(limit), Some(time_per_sample)) = (options.time_limit, time_per_sample) {
+            if start.elapsed() + time_per_sample * total as u32 > limit {
+                break;
+            }
+        }
 
-    // Render
-    println!("P3\n{} {}\n255", image_width, image_height);
+        let pass_start = Instant::now();
+        schedule.render_pass(&mut framebuffer, &samples, sampler_kind, seed, sample);
+        time_per_sample = Some(pass_start.elapsed() / total as u32);
+        pass += 1;
 
-    for j in (0..image_height).rev() {
-        eprint!("\rScanlines remaining: {} ", j);
+        let noise = framebuffer.noise();
+        let taken = framebuffer.samples.iter().map(|n| *n as u64).sum::<u64>();
+        eprint!(
+            "\r{:.1} samples per pixel, noise {:.4}, {:.1}s ",
+            taken as f64 / (width * height) as f64,
+            noise,
+            start.elapsed().as_secs_f64()
+        );
         std::io::stderr().flush().unwrap();
-        (0..image_width)
-            .into_par_iter()
-            .map(|i| {
-                let mut pixel_color = Color::zero();
-                for s in 0..samples_per_pixel {
-                    let u = (i as f64 + utils::random()) / (image_width - 1) as f64;
-                    let v = (j as f64 + utils::random()) / (image_height - 1) as f64;
-                    let r = cam.get_ray(u, v);
-                    pixel_color += ray_color(&r, &world, max_depth);
-                }
-                pixel_color
-            })
-            .collect::<Vec<Color>>()
-            .into_iter()
-            .for_each(|pixel_color| write_color(&pixel_color, samples_per_pixel));
+
+        if options.noise_target.is_some_and(|target| noise <= target) {
+            break;
+        }
+
+        if last_write.elapsed() >= write_interval {
+            if let Some(path) = &output {
+                save(&display(render(&framebuffer)), Some(path), precision);
+            }
+            save_checkpoint(&framebuffer, pass);
+            last_write = Instant::now();
+        }
     }
 
+    save_checkpoint(&framebuffer, pass);
+    if let Some(path) = &options.heatmap {
+        save(&framebuffer.heatmap(), Some(path), precision);
+    }
+    match &output {
+        Some(path) if !aovs.is_empty() => {
+            let light = framebuffer.direct_and_indirect();
+            let images = aovs
+                .iter()
+                .map(|aov| {
+                    let image = match (aov, &light, &first_hits) {
+                        (Aov::Direct, Some((direct, _)), _) => direct.clone(),
+                        (Aov::Indirect, Some((_, indirect)), _) => indirect.clone(),
+                        (_, _, Some(first_hits)) => first_hits.image(*aov).unwrap(),
+                        _ => unreachable!(),
+                    };
+                    (*aov, image)
+                })
+                .collect::<Vec<_>>();
+            let image = render(&framebuffer);
+            save_aovs(
+                path,
+                &image,
+                &display(image.clone()),
+                &images,
+                &tone_map,
+                precision,
+            );
+        }
+        _ => save(&display(render(&framebuffer)), output.as_deref(), precision),
+    }
     eprint!("\nDone.\n");
 }
+
+#[test]
+fn test_start_framebuffer() {
+    let checkpoint = |framebuffer| {
+        Some(Checkpoint {
+            args: Vec::new(),
+            seed: 1,
+            pass: 3,
+            framebuffer,
+        })
+    };
+
+    // Resumed without the direct light asked for, it's still kept.
+    let (framebuffer, pass) =
+        start_framebuffer(checkpoint(Framebuffer::with_direct(4, 2)), 4, 2, false).unwrap();
+    assert!(framebuffer.direct.is_some());
+    assert_eq!(pass, 3);
+
+    let err = start_framebuffer(checkpoint(Framebuffer::new(4, 2)), 4, 2, true)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err,
+        "the checkpoint doesn't keep direct light apart, which --aov direct and indirect need"
+    );
+    let err = start_framebuffer(checkpoint(Framebuffer::new(4, 2)), 2, 4, false)
+        .err()
+        .unwrap();
+    assert_eq!(err, "the checkpoint is for a 4x2 image, not 2x4");
+
+    let (split, _) = start_framebuffer(None, 4, 2, true).unwrap();
+    assert!(split.direct.is_some());
+    let (plain, _) = start_framebuffer(None, 4, 2, false).unwrap();
+    assert!(plain.direct.is_none());
+}
@@ -1,5 +1,6 @@
-use super::Material;
-use crate::{ray::Ray, shapes::hittable::HitRecord, vec3::Color, vec3::Vec3};
+use super::{Material, ScatterRecord};
+use crate::sampler::Sampler;
+use crate::{ray::Ray, shapes::hittable::HitRecord, vec3::Color};
 
 #[derive(Clone, Copy)]
 pub struct Dielectric {
@@ -11,43 +12,56 @@ impl Material for Dielectric {
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        *attenuation = Color::ones();
-        let etai_over_etat = if rec.front_face {
-            1.0 / self.ref_idx
-        } else {
-            self.ref_idx
-        };
-
-        let unit_direction = r_in.direction.unit();
-
-        let cos_theta = (-unit_direction).dot(&rec.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-
-        if etai_over_etat * sin_theta > 1.0 {
-            // Must reflect.
-            let reflected = unit_direction.reflect(&rec.normal);
-            *scattered = Ray::new(rec.p, reflected);
-            return true;
-        }
-        // Can refract.
-
-        let reflect_prob = schlick(cos_theta, etai_over_etat);
-        if crate::utils::random() < reflect_prob {
-            let reflected = unit_direction.reflect(&rec.normal);
-            *scattered = Ray::new(rec.p, reflected);
-            return true;
-        }
-
-        let refracted = unit_direction.refract(&rec.normal, etai_over_etat);
-        *scattered = Ray::new(rec.p, refracted);
-
-        true
+        refract_or_reflect(self.ref_idx, r_in, rec, srec, sampler)
     }
 }
 
+/// Refracts or, as often as Fresnel says, reflects `r_in` at a surface with
+/// index of refraction `ref_idx`. Shared with `Dispersive`.
+pub(crate) fn refract_or_reflect(
+    ref_idx: f64,
+    r_in: &Ray,
+    rec: &HitRecord,
+    srec: &mut ScatterRecord,
+    sampler: &mut dyn Sampler,
+) -> bool {
+    srec.attenuation = Color::ones();
+    srec.pdf = None;
+    let etai_over_etat = if rec.front_face {
+        1.0 / ref_idx
+    } else {
+        ref_idx
+    };
+
+    let unit_direction = r_in.direction.unit();
+
+    let cos_theta = (-unit_direction).dot(&rec.normal).min(1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    if etai_over_etat * sin_theta > 1.0 {
+        // Must reflect.
+        let reflected = unit_direction.reflect(&rec.normal);
+        srec.specular_ray = Some(Ray::new(rec.p, reflected, r_in.time));
+        return true;
+    }
+    // Can refract.
+
+    let reflect_prob = schlick(cos_theta, etai_over_etat);
+    if sampler.get_1d() < reflect_prob {
+        let reflected = unit_direction.reflect(&rec.normal);
+        srec.specular_ray = Some(Ray::new(rec.p, reflected, r_in.time));
+        return true;
+    }
+
+    let refracted = unit_direction.refract(&rec.normal, etai_over_etat);
+    srec.specular_ray = Some(Ray::new(rec.p, refracted, r_in.time));
+
+    true
+}
+
 fn schlick(cosine: f64, ref_idx: f64) -> f64 {
     let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     r0 = r0 * r0;
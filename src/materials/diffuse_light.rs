@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use super::{Material, ScatterRecord};
+use crate::sampler::Sampler;
+use crate::textures::solid_color::SolidColor;
+use crate::textures::Texture;
+use crate::{ray::Ray, shapes::hittable::HitRecord, vec3::Color, vec3::Point3};
+
+/// Emits light evenly in every direction and doesn't reflect anything.
+pub struct DiffuseLight {
+    pub emit: Arc<dyn Texture + Sync + Send>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> DiffuseLight {
+        DiffuseLight {
+            emit: Arc::new(SolidColor::new(emit)),
+        }
+    }
+
+    pub fn from_texture(emit: Arc<dyn Texture + Sync + Send>) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        false
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.emit.value(u, v, p)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::zero()
+    }
+}
@@ -0,0 +1,88 @@
+use super::dielectric::refract_or_reflect;
+use super::{Material, ScatterRecord};
+use crate::sampler::Sampler;
+use crate::spectrum::LAMBDA_D;
+use crate::{ray::Ray, shapes::hittable::HitRecord};
+
+/// How the index of refraction changes with the wavelength.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dispersion {
+    /// `n = a + b / λ²` with λ in micrometres. Good enough for most glasses
+    /// in the visible range.
+    Cauchy { a: f64, b: f64 },
+    /// `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)` with λ in micrometres, the form glass
+    /// catalogs give their coefficients in.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Schott N-BK7, ordinary crown glass.
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+
+    /// Schott SF11, a dense flint glass with strong dispersion.
+    pub const SF11: Dispersion = Dispersion::Sellmeier {
+        b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+        c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+    };
+
+    /// Index of refraction at `wavelength` nanometres.
+    pub fn index(&self, wavelength: f64) -> f64 {
+        let l2 = (wavelength / 1000.0).powi(2);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+/// Glass whose index of refraction depends on the wavelength, so a prism
+/// splits white light into a rainbow. This only shows in spectral renders,
+/// in RGB the index at the d line (587.6nm) is used for all light.
+#[derive(Clone, Copy)]
+pub struct Dispersive {
+    pub dispersion: Dispersion,
+}
+
+impl Dispersive {
+    pub fn new(dispersion: Dispersion) -> Dispersive {
+        Dispersive { dispersion }
+    }
+}
+
+impl Material for Dispersive {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let ref_idx = self.dispersion.index(r_in.wavelength.unwrap_or(LAMBDA_D));
+        srec.wavelength_dependent = r_in.wavelength.is_some();
+        refract_or_reflect(ref_idx, r_in, rec, srec, sampler)
+    }
+}
+
+#[test]
+fn test_dispersion() {
+    // Published indices at the d line.
+    assert!((Dispersion::BK7.index(LAMBDA_D) - 1.5168).abs() < 1e-4);
+    assert!((Dispersion::SF11.index(LAMBDA_D) - 1.7847).abs() < 1e-4);
+
+    // Blue bends more than red.
+    for dispersion in &[
+        Dispersion::BK7,
+        Dispersion::Cauchy {
+            a: 1.5046,
+            b: 0.00420,
+        },
+    ] {
+        assert!(dispersion.index(450.0) > dispersion.index(650.0));
+    }
+}
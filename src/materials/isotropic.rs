@@ -0,0 +1,50 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use super::{Material, ScatterRecord};
+use crate::pdf::SpherePdf;
+use crate::sampler::Sampler;
+use crate::textures::solid_color::SolidColor;
+use crate::textures::Texture;
+use crate::{ray::Ray, shapes::hittable::HitRecord, vec3::Color};
+
+/// Phase function for participating media: scatters into a uniformly random
+/// direction, no matter where the ray came from.
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture + Sync + Send>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Isotropic {
+        Isotropic {
+            albedo: Arc::new(SolidColor::new(albedo)),
+        }
+    }
+
+    pub fn from_texture(albedo: Arc<dyn Texture + Sync + Send>) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        srec.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        srec.pdf = Some(Box::new(SpherePdf));
+        srec.specular_ray = None;
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p)
+    }
+}
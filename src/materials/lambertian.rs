@@ -1,22 +1,53 @@
-use super::Material;
-use crate::{ray::Ray, shapes::hittable::HitRecord, vec3::Color, vec3::Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use super::{Material, ScatterRecord};
+use crate::pdf::CosinePdf;
+use crate::sampler::Sampler;
+use crate::textures::solid_color::SolidColor;
+use crate::textures::Texture;
+use crate::{ray::Ray, shapes::hittable::HitRecord, vec3::Color};
 
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture + Sync + Send>,
 }
 
+impl Lambertian {
+    pub fn new(albedo: Color) -> Lambertian {
+        Lambertian {
+            albedo: Arc::new(SolidColor::new(albedo)),
+        }
+    }
+
+    pub fn from_texture(albedo: Arc<dyn Texture + Sync + Send>) -> Lambertian {
+        Lambertian { albedo }
+    }
+}
 
 impl Material for Lambertian {
     fn scatter(
         &self,
-        r_in: &Ray,
+        _r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
     ) -> bool {
-        let scatter_direction = rec.normal + Vec3::random_unit_vector();
-        *scattered = Ray::new(rec.p, scatter_direction);
-        *attenuation = self.albedo;
+        srec.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        srec.pdf = Some(Box::new(CosinePdf::new(&rec.normal)));
+        srec.specular_ray = None;
         true
     }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = rec.normal.dot(&scattered.direction.unit());
+        if cosine < 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p)
+    }
 }
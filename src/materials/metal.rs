@@ -1,23 +1,51 @@
-use super::Material;
+use std::sync::Arc;
+
+use super::{Material, ScatterRecord};
+use crate::sampler::Sampler;
+use crate::textures::solid_color::SolidColor;
+use crate::textures::Texture;
 use crate::{ray::Ray, shapes::hittable::HitRecord, vec3::Color, vec3::Vec3};
 
 pub struct Metal {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture + Sync + Send>,
     pub fuzz: f64,
 }
 
+impl Metal {
+    pub fn new(albedo: Color, fuzz: f64) -> Metal {
+        Metal {
+            albedo: Arc::new(SolidColor::new(albedo)),
+            fuzz,
+        }
+    }
+
+    pub fn from_texture(albedo: Arc<dyn Texture + Sync + Send>, fuzz: f64) -> Metal {
+        Metal { albedo, fuzz }
+    }
+}
+
 impl Material for Metal {
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool {
         let reflected = r_in.direction.unit().reflect(&rec.normal);
-        *scattered = Ray::new(rec.p, reflected + self.fuzz * Vec3::random_in_unit_sphere());
-        *attenuation = self.albedo;
+        let scattered = Ray::new(
+            rec.p,
+            reflected + self.fuzz * Vec3::random_in_unit_sphere(sampler),
+            r_in.time,
+        );
+        srec.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        srec.pdf = None;
+        srec.specular_ray = Some(scattered);
 
         scattered.direction.dot(&rec.normal) > 0.0
     }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p)
+    }
 }
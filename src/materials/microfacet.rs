@@ -0,0 +1,253 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use super::{Material, ScatterRecord};
+use crate::onb::Onb;
+use crate::pdf::Pdf;
+use crate::sampler::Sampler;
+use crate::textures::solid_color::SolidColor;
+use crate::textures::Texture;
+use crate::{ray::Ray, shapes::hittable::HitRecord, vec3::Color, vec3::Vec3};
+
+/// Physically based material: a GGX microfacet specular layer (Smith masking,
+/// Schlick Fresnel) over a Lambertian base. `metallic` blends between a
+/// dielectric like plastic, with white highlights over a colored base, and a
+/// metal, which tints its reflection and has no diffuse part.
+pub struct Microfacet {
+    pub base_color: Arc<dyn Texture + Sync + Send>,
+    /// 0 is a mirror, 1 is very rough.
+    pub roughness: f64,
+    pub metallic: f64,
+}
+
+/// GGX gets numerically unstable for perfectly smooth surfaces.
+const MIN_ALPHA: f64 = 1e-3;
+
+impl Microfacet {
+    pub fn new(base_color: Color, roughness: f64, metallic: f64) -> Microfacet {
+        Microfacet::from_texture(Arc::new(SolidColor::new(base_color)), roughness, metallic)
+    }
+
+    pub fn from_texture(
+        base_color: Arc<dyn Texture + Sync + Send>,
+        roughness: f64,
+        metallic: f64,
+    ) -> Microfacet {
+        Microfacet {
+            base_color,
+            roughness,
+            metallic,
+        }
+    }
+
+    fn alpha(&self) -> f64 {
+        (self.roughness * self.roughness).max(MIN_ALPHA)
+    }
+
+    /// Reflectance at normal incidence. Dielectrics reflect about 4%.
+    fn f0(&self, base_color: Color) -> Color {
+        (1.0 - self.metallic) * Color::new(0.04, 0.04, 0.04) + self.metallic * base_color
+    }
+}
+
+fn fresnel_schlick(f0: Color, cosine: f64) -> Color {
+    f0 + (Color::ones() - f0) * (1.0 - cosine).max(0.0).powi(5)
+}
+
+/// GGX normal distribution.
+fn ggx_d(n_dot_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+/// Smith masking for one direction.
+fn smith_g1(n_dot_v: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    2.0 * n_dot_v / (n_dot_v + (a2 + (1.0 - a2) * n_dot_v * n_dot_v).sqrt())
+}
+
+impl Material for Microfacet {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        let wo = -r_in.direction.unit();
+        let n_dot_wo = rec.normal.dot(&wo);
+        if n_dot_wo <= 0.0 {
+            return false;
+        }
+
+        let base_color = self.base_color.value(rec.u, rec.v, &rec.p);
+
+        // Sample the specular lobe about as often as it contributes.
+        let average = |c: Color| (c.x + c.y + c.z) / 3.0;
+        let specular = average(fresnel_schlick(self.f0(base_color), n_dot_wo));
+        let diffuse = (1.0 - self.metallic) * average(base_color) * (1.0 - specular);
+        let specular_probability = if specular + diffuse > 0.0 {
+            (specular / (specular + diffuse)).max(0.1)
+        } else {
+            1.0
+        };
+
+        srec.attenuation = base_color;
+        srec.pdf = Some(Box::new(MicrofacetPdf {
+            uvw: Onb::build_from_w(&rec.normal),
+            wo,
+            alpha: self.alpha(),
+            specular_probability,
+        }));
+        srec.specular_ray = None;
+        true
+    }
+
+    fn scattering(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let n = rec.normal;
+        let wo = -r_in.direction.unit();
+        let wi = scattered.direction.unit();
+        let n_dot_wo = n.dot(&wo);
+        let n_dot_wi = n.dot(&wi);
+        if n_dot_wo <= 0.0 || n_dot_wi <= 0.0 {
+            return Color::zero();
+        }
+
+        let h = (wo + wi).unit();
+        let alpha = self.alpha();
+        let base_color = srec.attenuation;
+
+        let f = fresnel_schlick(self.f0(base_color), wi.dot(&h));
+        let d = ggx_d(n.dot(&h), alpha);
+        let g = smith_g1(n_dot_wo, alpha) * smith_g1(n_dot_wi, alpha);
+
+        // Both already multiplied by the cosine n . wi.
+        let specular = f * (d * g / (4.0 * n_dot_wo));
+        let diffuse = (1.0 - self.metallic) * (Color::ones() - f) * base_color * (n_dot_wi / PI);
+
+        specular + diffuse
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base_color.value(rec.u, rec.v, &rec.p)
+    }
+}
+
+/// Mix of sampling microfacet normals from the GGX distribution D(h), then
+/// reflecting about them, and cosine sampling for the diffuse part.
+struct MicrofacetPdf {
+    uvw: Onb,
+    wo: Vec3,
+    alpha: f64,
+    specular_probability: f64,
+}
+
+impl Pdf for MicrofacetPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let wi = direction.unit();
+        let n = self.uvw.w;
+        let n_dot_wi = n.dot(&wi);
+        if n_dot_wi <= 0.0 {
+            return 0.0;
+        }
+
+        // Density of the half vector, converted to the reflected direction.
+        let h = (self.wo + wi).unit();
+        let n_dot_h = n.dot(&h);
+        let specular = ggx_d(n_dot_h, self.alpha) * n_dot_h / (4.0 * self.wo.dot(&h).abs());
+        let diffuse = n_dot_wi / PI;
+
+        self.specular_probability * specular + (1.0 - self.specular_probability) * diffuse
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.get_1d() >= self.specular_probability {
+            return self.uvw.local(&Vec3::random_cosine_direction(sampler));
+        }
+
+        let r1 = sampler.get_1d();
+        let r2 = sampler.get_1d();
+        let a2 = self.alpha * self.alpha;
+        let cos_theta = ((1.0 - r1) / (1.0 + (a2 - 1.0) * r1)).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * r2;
+        let h = self.uvw.local(&Vec3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            cos_theta,
+        ));
+
+        (-self.wo).reflect(&h)
+    }
+}
+
+#[test]
+fn test_microfacet_sampling() {
+    use crate::pdf::SpherePdf;
+    use crate::utils::Rng;
+    use crate::vec3::Point3;
+
+    let mut rec = HitRecord::new();
+    rec.normal = Vec3::new(0.0, 0.0, 1.0);
+    rec.front_face = true;
+    let r_in = Ray::new(Point3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0), 0.0);
+
+    let mut rng = Rng::new(1);
+    for &(roughness, metallic) in &[(0.5, 1.0), (0.8, 0.0), (0.6, 0.5)] {
+        let material = Microfacet::new(Color::new(0.9, 0.6, 0.3), roughness, metallic);
+        let mut srec = ScatterRecord::new();
+        assert!(material.scatter(&r_in, &rec, &mut srec, &mut rng));
+        let pdf = srec.pdf.as_ref().unwrap();
+
+        // The density integrates to one over the sphere, minus the reflections
+        // that end up below the surface.
+        let n = 50_000;
+        let total: f64 = (0..n)
+            .map(|_| pdf.value(&Vec3::random_unit_vector(&mut rng)) * 4.0 * PI)
+            .sum();
+        let total = total / n as f64;
+        assert!(total > 0.8 && total < 1.01, "{}", total);
+
+        // Importance sampling and uniform sampling agree on how much light
+        // is reflected, and it's never more than comes in.
+        let reflected = |direction: Vec3, density: f64| {
+            let scattered = Ray::new(Point3::zero(), direction, 0.0);
+            material.scattering(&r_in, &rec, &srec, &scattered).x / density
+        };
+        let sampled: f64 = (0..n)
+            .map(|_| {
+                let d = pdf.generate(&mut rng);
+                let density = pdf.value(&d);
+                if density > 0.0 {
+                    reflected(d, density)
+                } else {
+                    0.0
+                }
+            })
+            .sum::<f64>()
+            / n as f64;
+        let uniform: f64 = (0..n)
+            .map(|_| {
+                reflected(
+                    Vec3::random_unit_vector(&mut rng),
+                    SpherePdf.value(&Vec3::zero()),
+                )
+            })
+            .sum::<f64>()
+            / n as f64;
+        assert!(sampled <= 1.0);
+        assert!(
+            (sampled - uniform).abs() < 0.05 * uniform,
+            "{} != {}",
+            sampled,
+            uniform
+        );
+    }
+}
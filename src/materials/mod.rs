@@ -1,17 +1,87 @@
+use crate::pdf::Pdf;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::shapes::hittable::HitRecord;
-use crate::vec3::Color;
+use crate::vec3::{Color, Point3};
 
 pub mod dielectric;
+pub mod diffuse_light;
+pub mod dispersive;
+pub mod isotropic;
 pub mod lambertian;
 pub mod metal;
+pub mod microfacet;
+
+/// How a material scatters an incoming ray.
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    /// Distribution of the scattered directions. The renderer samples it
+    /// together with the lights and weights the result with
+    /// `Material::scattering_pdf`.
+    pub pdf: Option<Box<dyn Pdf>>,
+    /// Used instead of `pdf` by specular materials, which scatter in a single
+    /// direction that can't be importance sampled.
+    pub specular_ray: Option<Ray>,
+    /// Set when the scattered direction depends on the ray's wavelength, so
+    /// a spectral render can only follow the hero wavelength from here on.
+    pub wavelength_dependent: bool,
+}
+
+impl Default for ScatterRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScatterRecord {
+    pub fn new() -> ScatterRecord {
+        ScatterRecord {
+            attenuation: Color::zero(),
+            pdf: None,
+            specular_ray: None,
+            wavelength_dependent: false,
+        }
+    }
+}
 
 pub trait Material: Sync + Send {
+    /// Returns false if the ray is absorbed.
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool;
+
+    /// Density of scattering from `r_in` into `scattered`, per unit solid
+    /// angle. Only called for materials that set `ScatterRecord::pdf`.
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    /// How much of the light arriving along `scattered` leaves along `r_in`
+    /// (the BSDF times the cosine). The default is right for materials whose
+    /// color doesn't depend on the directions.
+    fn scattering(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        srec.attenuation * self.scattering_pdf(r_in, rec, scattered)
+    }
+
+    /// Light given off by the surface at the hit point. Most materials don't
+    /// emit anything.
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::zero()
+    }
+
+    /// The surface color at the hit point, without any lighting, for
+    /// compositing and denoising. White for clear materials like glass.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::ones()
+    }
 }
@@ -0,0 +1,579 @@
+//! Wavefront OBJ and MTL import.
+//!
+//! Faces are grouped by their group (`g`/`o`) and material (`usemtl`), each
+//! group becomes its own `TriangleMesh`. Polygons with more than three
+//! vertices are triangulated as a fan, which is right for the convex faces
+//! modelling tools export.
+//!
+//! MTL materials are mapped onto the materials this crate has:
+//!
+//! * a material with `d` below 1 (or `Tr` above 0) or an `illum` model that
+//!   asks for refraction becomes a `Dielectric` using `Ni` as the index of
+//!   refraction,
+//! * one whose specular color `Ks` is brighter than its diffuse color `Kd`
+//!   becomes a `Metal` with albedo `Ks` and a fuzz derived from the specular
+//!   exponent `Ns`,
+//! * everything else is a `Lambertian` with albedo `Kd`, or the image in
+//!   `map_Kd` if there is one.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::materials::dielectric::Dielectric;
+use crate::materials::lambertian::Lambertian;
+use crate::materials::metal::Metal;
+use crate::materials::Material;
+use crate::shapes::hittable_list::HittableList;
+use crate::shapes::triangle::Uv;
+use crate::shapes::triangle_mesh::{MeshFace, TriangleMesh};
+use crate::textures::image_texture::ImageTexture;
+use crate::vec3::{Color, Point3, Vec3};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, std::io::Error),
+    Parse {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(file, e) => write!(f, "{}: {}", file.display(), e),
+            ObjError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// A material as written in an MTL file, before it is mapped onto one of the
+/// crate's materials.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub kd: Color,
+    pub ks: Color,
+    pub ns: f64,
+    pub ni: f64,
+    pub d: f64,
+    pub illum: u32,
+    /// Diffuse texture, relative to the directory the MTL file is in.
+    pub map_kd: Option<PathBuf>,
+}
+
+impl MtlMaterial {
+    fn new(name: &str) -> MtlMaterial {
+        MtlMaterial {
+            name: name.to_string(),
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: Color::zero(),
+            ns: 0.0,
+            ni: 1.0,
+            d: 1.0,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+
+    /// Loads the diffuse texture if there is one, so this can fail.
+    pub fn to_material(&self) -> Result<Arc<dyn Material + Sync + Send>, ObjError> {
+        let max = |c: &Color| c.x.max(c.y).max(c.z);
+
+        // Illumination models 4, 6, 7 and 9 are the transparent ones.
+        let transparent = self.d < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9);
+        if transparent {
+            let ref_idx = if self.ni > 1.0 { self.ni } else { 1.5 };
+            Ok(Arc::new(Dielectric { ref_idx }))
+        } else if max(&self.ks) > max(&self.kd) {
+            // Phong exponents map to roughness as sqrt(2 / (Ns + 2)), which is
+            // close enough to what fuzz means for a metal.
+            let fuzz = (2.0 / (self.ns.max(0.0) + 2.0)).sqrt().min(1.0);
+            Ok(Arc::new(Metal::new(self.ks, fuzz)))
+        } else if let Some(path) = &self.map_kd {
+            let texture =
+                ImageTexture::load(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+            Ok(Arc::new(Lambertian::from_texture(Arc::new(texture))))
+        } else {
+            Ok(Arc::new(Lambertian::new(self.kd)))
+        }
+    }
+}
+
+struct Parser<'a> {
+    file: &'a Path,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: String) -> Result<T, ObjError> {
+        Err(ObjError::Parse {
+            file: self.file.to_path_buf(),
+            line: self.line,
+            message,
+        })
+    }
+
+    fn floats(&self, keyword: &str, args: &[&str], min: usize) -> Result<Vec<f64>, ObjError> {
+        if args.len() < min {
+            return self.error(format!(
+                "`{}` needs at least {} numbers, got {}",
+                keyword,
+                min,
+                args.len()
+            ));
+        }
+        args.iter()
+            .map(|a| match a.parse::<f64>() {
+                Ok(x) => Ok(x),
+                Err(_) => self.error(format!("`{}`: `{}` is not a number", keyword, a)),
+            })
+            .collect()
+    }
+
+    fn color(&self, keyword: &str, args: &[&str]) -> Result<Color, ObjError> {
+        let c = self.floats(keyword, args, 1)?;
+        // A single value is a grey.
+        Ok(match c.len() {
+            1 | 2 => Color::new(c[0], c[0], c[0]),
+            _ => Color::new(c[0], c[1], c[2]),
+        })
+    }
+
+    /// OBJ indices start at 1, negative ones count back from the end.
+    fn index(&self, s: &str, len: usize, what: &str) -> Result<usize, ObjError> {
+        let i: i64 = match s.parse() {
+            Ok(i) => i,
+            Err(_) => return self.error(format!("`{}` is not a valid {} index", s, what)),
+        };
+
+        let resolved = if i > 0 { i - 1 } else { len as i64 + i };
+        if i == 0 || resolved < 0 || resolved >= len as i64 {
+            return self.error(format!(
+                "{} index {} is out of range, there are {}",
+                what, i, len
+            ));
+        }
+
+        Ok(resolved as usize)
+    }
+}
+
+/// Parses the contents of an MTL file.
+pub fn parse_mtl(source: &str, file: &Path) -> Result<Vec<MtlMaterial>, ObjError> {
+    let mut parser = Parser { file, line: 0 };
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+
+    for (n, line) in source.lines().enumerate() {
+        parser.line = n + 1;
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let args: Vec<&str> = words.collect();
+
+        if keyword == "newmtl" {
+            let name = args.join(" ");
+            if name.is_empty() {
+                return parser.error("`newmtl` needs a name".to_string());
+            }
+            materials.push(MtlMaterial::new(&name));
+            continue;
+        }
+
+        let current = match materials.last_mut() {
+            Some(m) => m,
+            // Anything before the first material has nothing to apply to.
+            None => continue,
+        };
+
+        match keyword {
+            "Kd" => current.kd = parser.color(keyword, &args)?,
+            "Ks" => current.ks = parser.color(keyword, &args)?,
+            "Ns" => current.ns = parser.floats(keyword, &args, 1)?[0],
+            "Ni" => current.ni = parser.floats(keyword, &args, 1)?[0],
+            "d" => current.d = parser.floats(keyword, &args, 1)?[0],
+            "Tr" => current.d = 1.0 - parser.floats(keyword, &args, 1)?[0],
+            "illum" => {
+                current.illum = match args.first().and_then(|a| a.parse().ok()) {
+                    Some(i) => i,
+                    None => return parser.error("`illum` needs a whole number".to_string()),
+                }
+            }
+            "map_Kd" => {
+                // Options like `-s` come before the file name, which is last.
+                match args.last() {
+                    Some(name) => {
+                        let base = file.parent().unwrap_or_else(|| Path::new(""));
+                        current.map_kd = Some(base.join(name));
+                    }
+                    None => return parser.error("`map_Kd` needs a file name".to_string()),
+                }
+            }
+            // Ambient color, emission and other texture maps aren't supported.
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+#[derive(Default)]
+struct Group {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Uv>,
+    faces: Vec<MeshFace>,
+    // Maps the file's indices to this group's buffers.
+    position_map: HashMap<usize, usize>,
+    normal_map: HashMap<usize, usize>,
+    uv_map: HashMap<usize, usize>,
+}
+
+fn remap<T: Copy>(
+    map: &mut HashMap<usize, usize>,
+    buffer: &mut Vec<T>,
+    all: &[T],
+    i: usize,
+) -> usize {
+    *map.entry(i).or_insert_with(|| {
+        buffer.push(all[i]);
+        buffer.len() - 1
+    })
+}
+
+/// A parsed OBJ file. Each mesh has the name of its group and the name of its
+/// material, either may be empty.
+pub struct ObjModel {
+    pub meshes: Vec<(String, String, TriangleMesh)>,
+}
+
+impl ObjModel {
+    /// All meshes in a single list, ready to be added to a scene.
+    pub fn into_list(self) -> HittableList {
+        let mut list = HittableList::new();
+        for (_, _, mesh) in self.meshes {
+            list.add(Box::new(mesh));
+        }
+        list
+    }
+}
+
+/// Reads an OBJ file and any MTL libraries it references (relative to the OBJ
+/// file). Faces without a material, or with one that can't be found, use
+/// `default_material`. So do the faces of a library that's missing.
+pub fn load_obj<P: AsRef<Path>>(
+    path: P,
+    default_material: Option<Arc<dyn Material + Sync + Send>>,
+) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+    parse_obj(&source, path, default_material, |name| {
+        let mtl_path = base.join(name);
+        let mtl =
+            std::fs::read_to_string(&mtl_path).map_err(|e| ObjError::Io(mtl_path.clone(), e))?;
+        parse_mtl(&mtl, &mtl_path)
+    })
+}
+
+/// Parses the contents of an OBJ file. `load_mtl` is called with the name
+/// given to every `mtllib` statement, a library it can't find adds no
+/// materials.
+pub fn parse_obj<F>(
+    source: &str,
+    file: &Path,
+    default_material: Option<Arc<dyn Material + Sync + Send>>,
+    mut load_mtl: F,
+) -> Result<ObjModel, ObjError>
+where
+    F: FnMut(&str) -> Result<Vec<MtlMaterial>, ObjError>,
+{
+    let mut parser = Parser { file, line: 0 };
+
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<Uv> = Vec::new();
+
+    let mut materials: HashMap<String, Arc<dyn Material + Sync + Send>> = HashMap::new();
+
+    // Groups in the order they first appear so the output is stable.
+    let mut order: Vec<(String, String)> = Vec::new();
+    let mut groups: HashMap<(String, String), Group> = HashMap::new();
+    let mut group_name = String::new();
+    let mut material_name = String::new();
+
+    for (n, line) in source.lines().enumerate() {
+        parser.line = n + 1;
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let args: Vec<&str> = words.collect();
+
+        match keyword {
+            "v" => {
+                let v = parser.floats(keyword, &args, 3)?;
+                positions.push(Point3::new(v[0], v[1], v[2]));
+            }
+            "vn" => {
+                let v = parser.floats(keyword, &args, 3)?;
+                let normal = Vec3::new(v[0], v[1], v[2]);
+                if normal.length_squared() == 0.0 {
+                    return parser.error("`vn`: the normal must not be zero".to_string());
+                }
+                normals.push(normal.unit());
+            }
+            "vt" => {
+                let v = parser.floats(keyword, &args, 1)?;
+                uvs.push((v[0], v.get(1).copied().unwrap_or(0.0)));
+            }
+            "g" | "o" => group_name = args.join(" "),
+            "usemtl" => material_name = args.join(" "),
+            "mtllib" => {
+                for name in &args {
+                    let library = match load_mtl(name) {
+                        Err(ObjError::Io(_, e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                            Vec::new()
+                        }
+                        library => library?,
+                    };
+                    for m in library {
+                        materials.insert(m.name.clone(), m.to_material()?);
+                    }
+                }
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return parser.error(format!("a face needs 3 vertices, got {}", args.len()));
+                }
+
+                let mut corners = Vec::with_capacity(args.len());
+                for arg in &args {
+                    let mut parts = arg.split('/');
+                    let p = parser.index(parts.next().unwrap_or(""), positions.len(), "vertex")?;
+                    let t = match parts.next() {
+                        Some(s) if !s.is_empty() => {
+                            Some(parser.index(s, uvs.len(), "texture coordinate")?)
+                        }
+                        _ => None,
+                    };
+                    let n = match parts.next() {
+                        Some(s) if !s.is_empty() => {
+                            Some(parser.index(s, normals.len(), "normal")?)
+                        }
+                        _ => None,
+                    };
+                    corners.push((p, t, n));
+                }
+
+                let key = (group_name.clone(), material_name.clone());
+                let group = groups.entry(key.clone()).or_insert_with(|| {
+                    order.push(key);
+                    Group::default()
+                });
+
+                let mut mapped = Vec::with_capacity(corners.len());
+                for &(p, t, n) in &corners {
+                    let p = remap(&mut group.position_map, &mut group.positions, &positions, p);
+                    let t = t.map(|t| remap(&mut group.uv_map, &mut group.uvs, &uvs, t));
+                    let n =
+                        n.map(|n| remap(&mut group.normal_map, &mut group.normals, &normals, n));
+                    mapped.push((p, t, n));
+                }
+
+                for i in 1..mapped.len() - 1 {
+                    let tri = [mapped[0], mapped[i], mapped[i + 1]];
+                    let all_uvs = tri.iter().all(|c| c.1.is_some());
+                    let all_normals = tri.iter().all(|c| c.2.is_some());
+                    group.faces.push(MeshFace {
+                        positions: tri.map(|c| c.0),
+                        uvs: if all_uvs {
+                            Some(tri.map(|c| c.1.unwrap()))
+                        } else {
+                            None
+                        },
+                        normals: if all_normals {
+                            Some(tri.map(|c| c.2.unwrap()))
+                        } else {
+                            None
+                        },
+                    });
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry.
+            _ => {}
+        }
+    }
+
+    let meshes = order
+        .into_iter()
+        .map(|key| {
+            let group = groups.remove(&key).unwrap();
+            let mat_ptr = materials
+                .get(&key.1)
+                .cloned()
+                .or_else(|| default_material.clone());
+            let mesh = TriangleMesh::new(
+                group.positions,
+                group.normals,
+                group.uvs,
+                group.faces,
+                mat_ptr,
+            );
+            (key.0, key.1, mesh)
+        })
+        .collect();
+
+    Ok(ObjModel { meshes })
+}
+
+#[test]
+fn test_parse_obj() {
+    let obj = "\
+# A quad and a triangle in different groups.
+mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+g floor
+usemtl red
+f 1/1/1 2/2/1 3/3/1 4/4/1
+g wall
+usemtl glass
+f -4 -3 -2
+";
+    let mtl = "\
+newmtl red
+Kd 0.8 0.1 0.1
+newmtl glass
+Ni 1.5
+d 0.2
+";
+
+    let model = parse_obj(obj, Path::new("test.obj"), None, |name| {
+        assert_eq!(name, "scene.mtl");
+        parse_mtl(mtl, Path::new(name))
+    })
+    .unwrap();
+
+    assert_eq!(model.meshes.len(), 2);
+    assert_eq!(model.meshes[0].0, "floor");
+    assert_eq!(model.meshes[0].1, "red");
+    assert_eq!(model.meshes[0].2.len(), 2);
+    assert_eq!(model.meshes[1].0, "wall");
+    assert_eq!(model.meshes[1].2.len(), 1);
+}
+
+#[test]
+fn test_parse_mtl() {
+    let mtl = "\
+newmtl chrome
+Kd 0.1 0.1 0.1
+Ks 0.9 0.9 0.9
+Ns 200
+illum 3
+newmtl wood
+map_Kd -s 1 1 1 textures/wood.png
+";
+    let materials = parse_mtl(mtl, Path::new("models/test.mtl")).unwrap();
+    assert_eq!(materials.len(), 2);
+    assert_eq!(materials[0].name, "chrome");
+    assert_eq!(materials[0].ks, Color::new(0.9, 0.9, 0.9));
+    assert_eq!(materials[0].ns, 200.0);
+    assert_eq!(materials[0].illum, 3);
+    assert_eq!(materials[0].map_kd, None);
+    assert_eq!(
+        materials[1].map_kd,
+        Some(PathBuf::from("models/textures/wood.png"))
+    );
+}
+
+#[test]
+fn test_parse_obj_errors() {
+    let no_mtl = |_: &str| -> Result<Vec<MtlMaterial>, ObjError> { Ok(Vec::new()) };
+
+    let err = parse_obj(
+        "v 0 0 0\nv 1 0 0\nf 1 2 3\n",
+        Path::new("a.obj"),
+        None,
+        no_mtl,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "a.obj:3: vertex index 3 is out of range, there are 2"
+    );
+
+    let err = parse_obj("v 0 zero 0\n", Path::new("a.obj"), None, no_mtl)
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "a.obj:1: `v`: `zero` is not a number");
+
+    let err = parse_obj("v 0 0 0\nvn 0 0 0\n", Path::new("a.obj"), None, no_mtl)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "a.obj:2: `vn`: the normal must not be zero"
+    );
+}
+
+#[test]
+fn test_parse_obj_missing_mtl() {
+    use crate::ray::Ray;
+    use crate::shapes::hittable::{HitRecord, Hittable};
+    use crate::utils::Rng;
+
+    let default_material: Arc<dyn Material + Sync + Send> =
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let obj = "mtllib gone.mtl\nv -1 -1 0\nv 1 -1 0\nv 0 1 0\nusemtl red\nf 1 2 3\n";
+    let model = parse_obj(
+        obj,
+        Path::new("a.obj"),
+        Some(default_material.clone()),
+        |name| {
+            let e = std::io::Error::from(std::io::ErrorKind::NotFound);
+            Err(ObjError::Io(PathBuf::from(name), e))
+        },
+    )
+    .unwrap();
+
+    let r = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    let mut rec = HitRecord::new();
+    assert!(model.meshes[0]
+        .2
+        .hit(&r, 0.001, f64::INFINITY, &mut rec, &mut Rng::new(0)));
+    assert!(Arc::ptr_eq(
+        rec.mat_ptr.as_ref().unwrap(),
+        &default_material
+    ));
+
+    // Other errors still fail the model.
+    let err = parse_obj(obj, Path::new("a.obj"), None, |name| {
+        let e = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        Err(ObjError::Io(PathBuf::from(name), e))
+    })
+    .err()
+    .unwrap();
+    assert_eq!(err.to_string(), "gone.mtl: permission denied");
+}
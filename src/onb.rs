@@ -0,0 +1,30 @@
+use crate::vec3::Vec3;
+
+/// Orthonormal basis, used to turn directions generated around +z into
+/// directions around an arbitrary `w`.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn build_from_w(n: &Vec3) -> Onb {
+        let w = n.unit();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit();
+        let u = w.cross(&v);
+
+        Onb { u, v, w }
+    }
+
+    #[inline]
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+}
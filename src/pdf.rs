@@ -0,0 +1,129 @@
+//! Probability densities over directions, used to pick where a scattered
+//! ray goes. Sampling directions that matter more (e.g. towards lights) and
+//! dividing by the density keeps the estimate unbiased but reduces noise.
+
+use std::f64::consts::PI;
+
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::shapes::hittable::Hittable;
+use crate::vec3::{Point3, Vec3};
+
+pub trait Pdf {
+    /// Density of `direction`, per unit solid angle.
+    fn value(&self, direction: &Vec3) -> f64;
+
+    /// A random direction distributed according to `value`. It doesn't need
+    /// to be normalized.
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3;
+}
+
+/// Uniform over all directions.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::random_unit_vector(sampler)
+    }
+}
+
+/// Proportional to the cosine to `w`, which is exactly what a Lambertian
+/// surface with normal `w` scatters.
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(w: &Vec3) -> CosinePdf {
+        CosinePdf {
+            uvw: Onb::build_from_w(w),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = direction.unit().dot(&self.uvw.w);
+        if cosine <= 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.uvw.local(&Vec3::random_cosine_direction(sampler))
+    }
+}
+
+/// Directions from `origin` towards an object, usually the lights.
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: Point3) -> HittablePdf<'a> {
+        HittablePdf { objects, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.objects.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.objects.random(&self.origin, sampler)
+    }
+}
+
+/// Picks either density with equal probability. Sampling from the mixture
+/// and dividing by its combined density weights the two strategies with the
+/// balance heuristic, so each covers for the other's weak spots.
+pub struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> MixturePdf<'a> {
+        MixturePdf { p: [p0, p1] }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.get_1d() < 0.5 {
+            self.p[0].generate(sampler)
+        } else {
+            self.p[1].generate(sampler)
+        }
+    }
+}
+
+#[test]
+fn test_cosine_pdf() {
+    use crate::utils::Rng;
+    // Monte Carlo estimate of the integral of the density over the sphere,
+    // which has to be one.
+    let mut rng = Rng::new(1);
+    let pdf = CosinePdf::new(&Vec3::new(0.0, 1.0, 1.0));
+    let n = 100_000;
+    let total: f64 = (0..n)
+        .map(|_| pdf.value(&Vec3::random_unit_vector(&mut rng)) / SpherePdf.value(&Vec3::zero()))
+        .sum();
+    assert!((total / n as f64 - 1.0).abs() < 0.02);
+
+    for _ in 0..100 {
+        let d = pdf.generate(&mut rng);
+        assert!(pdf.value(&d) > 0.0);
+    }
+}
@@ -1,17 +1,33 @@
 use crate::vec3::{Point3, Vec3};
 
+#[derive(Clone, Copy, Debug)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    /// The moment the ray exists at, used for motion blur.
+    pub time: f64,
+    /// The hero wavelength in nanometres when rendering spectrally. Set by
+    /// the renderer on the ray that hits a material, so materials whose
+    /// behavior depends on it can look it up.
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
-    pub fn new(origin: Point3, direction: Point3) -> Ray {
-        return Ray { origin, direction };
+    pub fn new(origin: Point3, direction: Point3, time: f64) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+            wavelength: None,
+        }
+    }
+
+    pub fn with_wavelength(self, wavelength: Option<f64>) -> Ray {
+        Ray { wavelength, ..self }
     }
 
     pub fn at(&self, t: f64) -> Point3 {
-        return self.origin + t * self.direction;
+        self.origin + t * self.direction
     }
 }
 
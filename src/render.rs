@@ -1,30 +1,605 @@
-use crate::camera::Camera;
+use std::ops::{Add, Div, Mul};
+
+use crate::materials::ScatterRecord;
+use crate::pdf::{HittablePdf, MixturePdf, Pdf};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::shapes::hittable::{HitRecord, Hittable};
-use crate::shapes::hittable_list::HittableList;
-use crate::shapes::sphere::Sphere;
-use crate::vec3::{Color, Point3, Vec3};
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::vec3::Color;
+
+/// What a ray sees when it doesn't hit anything.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Background {
+    /// The white to light blue gradient from the first book.
+    Sky,
+    /// A single color. Black makes emissive objects the only light source.
+    Solid(Color),
+}
+
+impl Background {
+    pub fn value(&self, r: &Ray) -> Color {
+        match self {
+            Background::Sky => {
+                let unit_direction = r.direction.unit();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+            }
+            Background::Solid(color) => *color,
+        }
+    }
+}
+
+/// Radiance arriving along `r`. Diffuse bounces are sampled from a mix of
+/// the material's own distribution and directions towards `lights`, so small
+/// lights are found far more often than by bouncing around at random.
+pub fn ray_color(
+    r: &Ray,
+    background: &Background,
+    world: &dyn Hittable,
+    lights: Option<&dyn Hittable>,
+    depth: i32,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    trace(&Rgb, r, background, world, lights, depth, sampler)
+}
+
+/// Like `ray_color`, but for the given wavelengths only. Use
+/// `SampledWavelengths::to_rgb` to turn the result into a color.
+pub fn ray_spectrum(
+    r: &Ray,
+    wavelengths: &SampledWavelengths,
+    background: &Background,
+    world: &dyn Hittable,
+    lights: Option<&dyn Hittable>,
+    depth: i32,
+    sampler: &mut dyn Sampler,
+) -> SampledSpectrum {
+    let spectral = Spectral {
+        wavelengths,
+        dispersed: false,
+    };
+    trace(&spectral, r, background, world, lights, depth, sampler)
+}
+
+/// Like `ray_color`, but split into direct light and indirect light, which
+/// add up to the same. Direct light is what the camera sees straight from
+/// lights and the background, and what reached the first surface straight
+/// from them. Anything that bounced more often is indirect.
+pub fn ray_color_split(
+    r: &Ray,
+    background: &Background,
+    world: &dyn Hittable,
+    lights: Option<&dyn Hittable>,
+    depth: i32,
+    sampler: &mut dyn Sampler,
+) -> (Color, Color) {
+    let split = Split {
+        inner: Rgb,
+        max_depth: depth,
+    };
+    let value = trace(&split, r, background, world, lights, depth, sampler);
+    (value.direct, value.indirect)
+}
+
+/// `ray_spectrum` split like `ray_color_split`.
+pub fn ray_spectrum_split(
+    r: &Ray,
+    wavelengths: &SampledWavelengths,
+    background: &Background,
+    world: &dyn Hittable,
+    lights: Option<&dyn Hittable>,
+    depth: i32,
+    sampler: &mut dyn Sampler,
+) -> (SampledSpectrum, SampledSpectrum) {
+    let split = Split {
+        inner: Spectral {
+            wavelengths,
+            dispersed: false,
+        },
+        max_depth: depth,
+    };
+    let value = trace(&split, r, background, world, lights, depth, sampler);
+    (value.direct, value.indirect)
+}
+
+/// What radiance is tracked as: RGB or a few wavelengths.
+trait Radiance: Sized {
+    type Value: Copy
+        + Add<Output = Self::Value>
+        + Mul<Output = Self::Value>
+        + Div<f64, Output = Self::Value>;
+
+    fn zero(&self) -> Self::Value;
+    fn wavelength(&self) -> Option<f64>;
+    /// Converts a color from the scene.
+    fn upsample(&self, rgb: Color) -> Self::Value;
+    /// Converts light given off `depth` bounces before the end of the path.
+    fn emission(&self, rgb: Color, _depth: i32) -> Self::Value {
+        self.upsample(rgb)
+    }
+    /// What to trace with after a wavelength dependent scatter, or `None` if
+    /// nothing changes.
+    fn dispersed(&self) -> Option<Self>;
+    /// Weights light that went through the first wavelength dependent
+    /// scatter on the path.
+    fn disperse(&self, value: Self::Value) -> Self::Value;
+}
+
+struct Rgb;
+
+impl Radiance for Rgb {
+    type Value = Color;
+
+    fn zero(&self) -> Color {
+        Color::zero()
+    }
+
+    fn wavelength(&self) -> Option<f64> {
+        None
+    }
+
+    fn upsample(&self, rgb: Color) -> Color {
+        rgb
+    }
+
+    fn dispersed(&self) -> Option<Rgb> {
+        None
+    }
+
+    fn disperse(&self, value: Color) -> Color {
+        value
+    }
+}
+
+struct Spectral<'a> {
+    wavelengths: &'a SampledWavelengths,
+    /// Only the hero wavelength is still followed.
+    dispersed: bool,
+}
+
+impl<'a> Radiance for Spectral<'a> {
+    type Value = SampledSpectrum;
+
+    fn zero(&self) -> SampledSpectrum {
+        SampledSpectrum::zero()
+    }
+
+    fn wavelength(&self) -> Option<f64> {
+        Some(self.wavelengths.hero())
+    }
+
+    fn upsample(&self, rgb: Color) -> SampledSpectrum {
+        self.wavelengths.upsample(rgb)
+    }
+
+    fn dispersed(&self) -> Option<Spectral<'a>> {
+        if self.dispersed {
+            None
+        } else {
+            Some(Spectral {
+                wavelengths: self.wavelengths,
+                dispersed: true,
+            })
+        }
+    }
+
+    fn disperse(&self, value: SampledSpectrum) -> SampledSpectrum {
+        value.hero_only()
+    }
+}
+
+/// Tracks direct and indirect light separately, each as `R` does.
+struct Split<R> {
+    inner: R,
+    /// The depth the path started with, to tell how often light bounced.
+    max_depth: i32,
+}
+
+#[derive(Clone, Copy)]
+struct Pair<V> {
+    direct: V,
+    indirect: V,
+}
+
+impl<V: Add<Output = V>> Add for Pair<V> {
+    type Output = Pair<V>;
+
+    fn add(self, other: Pair<V>) -> Pair<V> {
+        Pair {
+            direct: self.direct + other.direct,
+            indirect: self.indirect + other.indirect,
+        }
+    }
+}
+
+impl<V: Mul<Output = V>> Mul for Pair<V> {
+    type Output = Pair<V>;
+
+    fn mul(self, other: Pair<V>) -> Pair<V> {
+        Pair {
+            direct: self.direct * other.direct,
+            indirect: self.indirect * other.indirect,
+        }
+    }
+}
+
+impl<V: Div<f64, Output = V>> Div<f64> for Pair<V> {
+    type Output = Pair<V>;
+
+    fn div(self, t: f64) -> Pair<V> {
+        Pair {
+            direct: self.direct / t,
+            indirect: self.indirect / t,
+        }
+    }
+}
+
+impl<R: Radiance> Radiance for Split<R> {
+    type Value = Pair<R::Value>;
+
+    fn zero(&self) -> Pair<R::Value> {
+        Pair {
+            direct: self.inner.zero(),
+            indirect: self.inner.zero(),
+        }
+    }
+
+    fn wavelength(&self) -> Option<f64> {
+        self.inner.wavelength()
+    }
 
-pub fn ray_color(r: &Ray, world: &HittableList, depth: i32) -> Color {
+    /// The same on both sides, so attenuation scales both.
+    fn upsample(&self, rgb: Color) -> Pair<R::Value> {
+        let value = self.inner.upsample(rgb);
+        Pair {
+            direct: value,
+            indirect: value,
+        }
+    }
+
+    fn emission(&self, rgb: Color, depth: i32) -> Pair<R::Value> {
+        let value = self.inner.emission(rgb, depth);
+        if depth >= self.max_depth - 1 {
+            Pair {
+                direct: value,
+                indirect: self.inner.zero(),
+            }
+        } else {
+            Pair {
+                direct: self.inner.zero(),
+                indirect: value,
+            }
+        }
+    }
+
+    fn dispersed(&self) -> Option<Split<R>> {
+        self.inner.dispersed().map(|inner| Split {
+            inner,
+            max_depth: self.max_depth,
+        })
+    }
+
+    fn disperse(&self, value: Pair<R::Value>) -> Pair<R::Value> {
+        Pair {
+            direct: self.inner.disperse(value.direct),
+            indirect: self.inner.disperse(value.indirect),
+        }
+    }
+}
+
+fn trace<R: Radiance>(
+    radiance: &R,
+    r: &Ray,
+    background: &Background,
+    world: &dyn Hittable,
+    lights: Option<&dyn Hittable>,
+    depth: i32,
+    sampler: &mut dyn Sampler,
+) -> R::Value {
     let mut rec = HitRecord::new();
 
     // If we've exceeded the ray bounce limit then we're done gathering light.
     if depth <= 0 {
-        return Color::zero();
+        return radiance.zero();
     }
 
-    if world.hit(r, 0.001, std::f64::INFINITY, &mut rec) {
-        let mut scattered = Ray::new(Vec3::zero(), Vec3::zero());
-        let mut attenuation = Color::zero();
-        if let Some(mat_ptr) = &rec.mat_ptr {
-            if mat_ptr.scatter(&r, &rec, &mut attenuation, &mut scattered) {
-                return attenuation * ray_color(&scattered, &world, depth - 1);
-            }
+    if !world.hit(r, 0.001, f64::INFINITY, &mut rec, sampler) {
+        return radiance.emission(background.value(r), depth);
+    }
+
+    let mat_ptr = match &rec.mat_ptr {
+        Some(mat_ptr) => mat_ptr,
+        None => return radiance.zero(),
+    };
+
+    let r = &r.with_wavelength(radiance.wavelength());
+    let mut srec = ScatterRecord::new();
+    let emitted = radiance.emission(mat_ptr.emitted(rec.u, rec.v, &rec.p), depth);
+
+    if !mat_ptr.scatter(r, &rec, &mut srec, sampler) {
+        return emitted;
+    }
+
+    let material_pdf = match &srec.pdf {
+        Some(pdf) => pdf.as_ref(),
+        None => {
+            return match &srec.specular_ray {
+                Some(specular) => {
+                    let incoming = scattered_radiance(
+                        radiance,
+                        srec.wavelength_dependent,
+                        specular,
+                        background,
+                        world,
+                        lights,
+                        depth,
+                        sampler,
+                    );
+                    emitted + radiance.upsample(srec.attenuation) * incoming
+                }
+                None => emitted,
+            };
+        }
+    };
+
+    let (scattered, pdf_value) = match lights {
+        Some(lights) => {
+            let light_pdf = HittablePdf::new(lights, rec.p);
+            let mixture = MixturePdf::new(&light_pdf, material_pdf);
+            let scattered = Ray::new(rec.p, mixture.generate(sampler), r.time);
+            (scattered, mixture.value(&scattered.direction))
         }
-        return Color::zero();
+        None => {
+            let scattered = Ray::new(rec.p, material_pdf.generate(sampler), r.time);
+            (scattered, material_pdf.value(&scattered.direction))
+        }
+    };
+    if pdf_value <= 0.0 {
+        return emitted;
+    }
+
+    let scattering = mat_ptr.scattering(r, &rec, &srec, &scattered);
+    if scattering == Color::zero() {
+        return emitted;
+    }
+
+    let incoming = scattered_radiance(
+        radiance,
+        srec.wavelength_dependent,
+        &scattered,
+        background,
+        world,
+        lights,
+        depth,
+        sampler,
+    );
+    emitted + radiance.upsample(scattering) * incoming / pdf_value
+}
+
+/// Radiance arriving along a scattered ray. After the first wavelength
+/// dependent scatter only the hero wavelength is followed.
+#[allow(clippy::too_many_arguments)]
+fn scattered_radiance<R: Radiance>(
+    radiance: &R,
+    wavelength_dependent: bool,
+    scattered: &Ray,
+    background: &Background,
+    world: &dyn Hittable,
+    lights: Option<&dyn Hittable>,
+    depth: i32,
+    sampler: &mut dyn Sampler,
+) -> R::Value {
+    match radiance.dispersed().filter(|_| wavelength_dependent) {
+        Some(dispersed) => radiance.disperse(trace(
+            &dispersed,
+            scattered,
+            background,
+            world,
+            lights,
+            depth - 1,
+            sampler,
+        )),
+        None => trace(
+            radiance,
+            scattered,
+            background,
+            world,
+            lights,
+            depth - 1,
+            sampler,
+        ),
+    }
+}
+
+#[test]
+fn test_ray_color_emission() {
+    use crate::materials::diffuse_light::DiffuseLight;
+    use crate::shapes::hittable_list::HittableList;
+    use crate::shapes::sphere::Sphere;
+    use crate::utils::Rng;
+    use crate::vec3::{Point3, Vec3};
+    use std::sync::Arc;
+
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -2.0),
+        1.0,
+        Some(Arc::new(DiffuseLight::new(Color::new(4.0, 2.0, 1.0)))),
+    )));
+
+    let mut rng = Rng::new(1);
+    let black = Background::Solid(Color::zero());
+    let toward = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    let away = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+    assert_eq!(
+        ray_color(&toward, &black, &world, None, 10, &mut rng),
+        Color::new(4.0, 2.0, 1.0)
+    );
+    assert_eq!(
+        ray_color(&away, &black, &world, None, 10, &mut rng),
+        Color::zero()
+    );
+    assert_eq!(
+        ray_color(&away, &Background::Sky, &world, None, 10, &mut rng),
+        Color::new(0.75, 0.85, 1.0)
+    );
+}
+
+#[test]
+fn test_ray_color_light_sampling() {
+    use crate::materials::diffuse_light::DiffuseLight;
+    use crate::materials::lambertian::Lambertian;
+    use crate::shapes::hittable_list::HittableList;
+    use crate::shapes::quad::Quad;
+    use crate::utils::Rng;
+    use crate::vec3::{Point3, Vec3};
+    use std::sync::Arc;
+
+    // A small light above a diffuse floor. Sampling the light has to give the
+    // same answer as bouncing at random, only with less noise.
+    let light = || {
+        Quad::xz_rect(
+            -0.5,
+            0.5,
+            -0.5,
+            0.5,
+            2.0,
+            Some(Arc::new(DiffuseLight::new(Color::new(10.0, 10.0, 10.0)))),
+        )
+    };
+    let mut world = HittableList::new();
+    world.add(Box::new(Quad::xz_rect(
+        -10.0,
+        10.0,
+        -10.0,
+        10.0,
+        0.0,
+        Some(Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))),
+    )));
+    world.add(Box::new(light()));
+    let mut lights = HittableList::new();
+    lights.add(Box::new(light()));
+
+    let black = Background::Solid(Color::zero());
+    let r = Ray::new(Point3::new(0.0, 1.0, 0.5), Vec3::new(0.0, -1.0, -0.5), 0.0);
+    let n = 100_000;
+    let mut rng = Rng::new(1);
+    let mut mean = |lights: Option<&dyn Hittable>| {
+        let total = (0..n).fold(Color::zero(), |total, _| {
+            total + ray_color(&r, &black, &world, lights, 2, &mut rng)
+        });
+        (total / n as f64).x
+    };
+
+    let bsdf_only = mean(None);
+    let with_lights = mean(Some(&lights));
+    assert!(bsdf_only > 0.0);
+    assert!(
+        (with_lights - bsdf_only).abs() < 0.05 * bsdf_only,
+        "{} != {}",
+        with_lights,
+        bsdf_only
+    );
+}
+
+#[test]
+fn test_ray_color_split() {
+    use crate::materials::lambertian::Lambertian;
+    use crate::shapes::hittable_list::HittableList;
+    use crate::shapes::sphere::Sphere;
+    use crate::utils::Rng;
+    use crate::vec3::{Point3, Vec3};
+    use std::sync::Arc;
+
+    // A ball on a big ball under the sky. Looking down at the ground next to
+    // the ball, some of the light comes off the ball.
+    let gray = || Some(Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))) as _);
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        gray(),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        gray(),
+    )));
+    let r = Ray::new(Point3::zero(), Vec3::new(0.6, -0.5, -1.0), 0.0);
+
+    let (mut direct, mut indirect) = (Color::zero(), Color::zero());
+    for seed in 0..1000 {
+        let total = ray_color(&r, &Background::Sky, &world, None, 10, &mut Rng::new(seed));
+        let (d, i) = ray_color_split(&r, &Background::Sky, &world, None, 10, &mut Rng::new(seed));
+        assert!((d + i - total).length() < 1e-9);
+        direct += d;
+        indirect += i;
     }
+    assert!(direct.x > 0.0 && indirect.x > 0.0);
+    assert!(direct.x > indirect.x);
+
+    // Seen straight away, the sky is direct light.
+    let up = Ray::new(Point3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.0);
+    let (d, i) = ray_color_split(&up, &Background::Sky, &world, None, 10, &mut Rng::new(1));
+    assert_eq!((d, i), (Background::Sky.value(&up), Color::zero()));
+}
+
+#[test]
+fn test_ray_color_is_deterministic() {
+    use crate::camera::Camera;
+    use crate::materials::dielectric::Dielectric;
+    use crate::materials::lambertian::Lambertian;
+    use crate::sampler::SamplerKind;
+    use crate::shapes::hittable_list::HittableList;
+    use crate::shapes::sphere::Sphere;
+    use crate::vec3::{Point3, Vec3};
+    use rayon::prelude::*;
+    use std::sync::Arc;
+
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        Some(Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        Some(Arc::new(Dielectric { ref_idx: 1.5 })),
+    )));
+    let cam = Camera::new(
+        Point3::zero(),
+        Point3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        90.0,
+        1.0,
+        0.1,
+        1.0,
+        0.0,
+        1.0,
+    );
 
-    let unit_direction = r.direction.unit();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+    // Every pixel has its own samples, so it doesn't matter how the
+    // pixels are spread over threads.
+    let render = |threads: usize| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            (0..64u64)
+                .into_par_iter()
+                .map(|pixel| {
+                    let (x, y) = ((pixel % 8) as u32, (pixel / 8) as u32);
+                    let mut sampler = SamplerKind::Sobol.create(7, 1);
+                    sampler.start_pixel_sample(x, y, 0);
+                    let r = cam.get_ray(x as f64 / 7.0, y as f64 / 7.0, &mut *sampler);
+                    ray_color(&r, &Background::Sky, &world, None, 10, &mut *sampler)
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(render(1), render(4));
 }
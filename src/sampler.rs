@@ -0,0 +1,524 @@
+//! Where the random numbers of a render come from. Each sample of a pixel
+//! asks its sampler for numbers one dimension at a time, in the same order:
+//! the position in the pixel (2D), the lens (2D), the time (1D), then
+//! whatever the bounces need. Samplers other than `Independent` spread the
+//! samples of a pixel evenly over each dimension, which converges faster
+//! than independent random numbers.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::utils::{mix_seed, Rng};
+
+pub trait Sampler {
+    /// Starts sample `index` of the pixel at `x`, `y`. Dimensions start over
+    /// from the first.
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32);
+
+    /// A number in [0, 1) for the next dimension.
+    fn get_1d(&mut self) -> f64;
+
+    /// A point in [0, 1)² for the next two dimensions.
+    fn get_2d(&mut self) -> (f64, f64) {
+        let u = self.get_1d();
+        (u, self.get_1d())
+    }
+}
+
+/// A plain `Rng` just carries on with its random numbers.
+impl Sampler for Rng {
+    fn start_pixel_sample(&mut self, _x: u32, _y: u32, _index: u32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        self.random()
+    }
+}
+
+/// The samplers that can be picked for a render.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    /// A sampler for a render with `seed`, which plans to take
+    /// `samples_per_pixel` samples per pixel. Only `Stratified` needs to
+    /// know, the others are good at any number of samples.
+    pub fn create(self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        let state = SampleState::new(seed);
+        match self {
+            SamplerKind::Independent => Box::new(Independent(state)),
+            SamplerKind::Stratified => Box::new(Stratified {
+                state,
+                samples_per_pixel: samples_per_pixel.max(1),
+            }),
+            SamplerKind::Halton => Box::new(Halton(state)),
+            SamplerKind::Sobol => Box::new(Sobol(state)),
+            SamplerKind::BlueNoise => Box::new(BlueNoise(state)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "blue-noise",
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SamplerKind, String> {
+        SamplerKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| {
+                let names = SamplerKind::ALL.map(|kind| kind.name());
+                format!("unknown sampler `{}`, use one of {}", s, names.join(", "))
+            })
+    }
+}
+
+/// What every sampler keeps track of.
+struct SampleState {
+    seed: u64,
+    x: u32,
+    y: u32,
+    index: u32,
+    dimension: u64,
+    /// Jitter, and dimensions a sampler has run out of.
+    rng: Rng,
+}
+
+impl SampleState {
+    fn new(seed: u64) -> SampleState {
+        SampleState {
+            seed,
+            x: 0,
+            y: 0,
+            index: 0,
+            dimension: 0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    fn start(&mut self, x: u32, y: u32, index: u32) {
+        self.x = x;
+        self.y = y;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Rng::new(mix_seed(self.pixel_hash(), index as u64));
+    }
+
+    fn pixel_hash(&self) -> u64 {
+        mix_seed(self.seed, (self.y as u64) << 32 | self.x as u64)
+    }
+
+    /// A hash of the seed, the pixel and the current dimension, then moves on
+    /// to the next dimension.
+    fn next_dimension(&mut self) -> u64 {
+        let hash = mix_seed(self.pixel_hash(), !self.dimension);
+        self.dimension += 1;
+        hash
+    }
+}
+
+/// Independent uniform random numbers.
+pub struct Independent(SampleState);
+
+impl Sampler for Independent {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.0.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.0.rng.random()
+    }
+}
+
+/// Splits every dimension into as many strata as there are samples per pixel
+/// and puts each sample in a different one, picked by a random permutation.
+/// In 2D the strata are a grid when the number of samples is a square.
+/// Samples past `samples_per_pixel` start another round of strata.
+pub struct Stratified {
+    state: SampleState,
+    samples_per_pixel: u32,
+}
+
+impl Stratified {
+    /// The stratum of the current sample out of `n`, for a dimension that
+    /// hashed to `hash`.
+    fn stratum(&self, n: u32, hash: u64) -> u32 {
+        let round = self.state.index / self.samples_per_pixel;
+        let seed = mix_seed(hash, round as u64) as u32;
+        permute(self.state.index % self.samples_per_pixel, n, seed)
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let n = self.samples_per_pixel;
+        let hash = self.state.next_dimension();
+        (self.stratum(n, hash) as f64 + self.state.rng.random()) / n as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let n = self.samples_per_pixel;
+        let side = (n as f64).sqrt() as u32;
+        if side * side != n {
+            let u = self.get_1d();
+            return (u, self.get_1d());
+        }
+
+        let hash = self.state.next_dimension();
+        self.state.dimension += 1;
+        let cell = self.stratum(n, hash);
+        let jitter = (self.state.rng.random(), self.state.rng.random());
+        (
+            ((cell % side) as f64 + jitter.0) / side as f64,
+            ((cell / side) as f64 + jitter.1) / side as f64,
+        )
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The Halton sequence, a prime base per dimension, shifted by a random
+/// amount per pixel and dimension (Cranley-Patterson rotation) so pixels
+/// don't all get the same numbers. Dimensions past the 32nd are
+/// independent random numbers.
+pub struct Halton(SampleState);
+
+impl Sampler for Halton {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.0.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.0.dimension as usize;
+        let hash = self.0.next_dimension();
+        match PRIMES.get(dimension) {
+            Some(base) => (radical_inverse(*base, self.0.index) + to_unit(hash)).fract(),
+            None => self.0.rng.random(),
+        }
+    }
+}
+
+/// Digits of `index` in `base`, mirrored around the decimal point.
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut scale = inverse_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f64 * scale;
+        index /= base;
+        scale *= inverse_base;
+    }
+    result
+}
+
+/// Owen-scrambled Sobol points, following Burley, "Practical Hash-based
+/// Owen Scrambling" (2020). Every dimension, or pair of dimensions for
+/// `get_2d`, uses the first two Sobol dimensions with its own shuffle and
+/// scrambling, so there is no limit on the number of dimensions.
+pub struct Sobol(SampleState);
+
+impl Sampler for Sobol {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.0.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.0.next_dimension();
+        sobol_1d(self.0.index, hash)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = self.0.next_dimension();
+        self.0.dimension += 1;
+        sobol_2d(self.0.index, hash)
+    }
+}
+
+fn sobol_1d(index: u32, hash: u64) -> f64 {
+    let i = nested_uniform_scramble(index, hash as u32);
+    to_unit_u32(nested_uniform_scramble(
+        i.reverse_bits(),
+        (hash >> 32) as u32,
+    ))
+}
+
+fn sobol_2d(index: u32, hash: u64) -> (f64, f64) {
+    let i = nested_uniform_scramble(index, hash as u32);
+    let seed = mix_seed(hash, 1);
+    (
+        to_unit_u32(nested_uniform_scramble(i.reverse_bits(), seed as u32)),
+        to_unit_u32(nested_uniform_scramble(
+            sobol_second(i),
+            (seed >> 32) as u32,
+        )),
+    )
+}
+
+/// The second dimension of the Sobol sequence.
+fn sobol_second(mut index: u32) -> u32 {
+    let mut v = 1 << 31;
+    let mut result = 0;
+    while index > 0 {
+        if index & 1 == 1 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+/// Sobol points that are the same for every pixel, shifted by a blue noise
+/// mask instead of random amounts. Neighboring pixels get very different
+/// shifts, so at low sample counts the error looks like fine grain rather
+/// than blotches.
+pub struct BlueNoise(SampleState);
+
+impl BlueNoise {
+    /// The mask at this pixel, moved by a different amount per dimension.
+    fn shift(&self, hash: u64) -> f64 {
+        let mask = blue_noise_mask();
+        let x = (self.0.x as u64 + hash) as usize % MASK_SIZE;
+        let y = (self.0.y as u64 + (hash >> 32)) as usize % MASK_SIZE;
+        mask[y * MASK_SIZE + x]
+    }
+
+    /// Like `SampleState::next_dimension`, but the same for every pixel.
+    fn next_dimension(&mut self) -> u64 {
+        let hash = mix_seed(self.0.seed, !self.0.dimension);
+        self.0.dimension += 1;
+        hash
+    }
+}
+
+impl Sampler for BlueNoise {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.0.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.next_dimension();
+        (sobol_1d(self.0.index, hash) + self.shift(hash)).fract()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = self.next_dimension();
+        self.0.dimension += 1;
+        let (u, v) = sobol_2d(self.0.index, hash);
+        (
+            (u + self.shift(hash)).fract(),
+            (v + self.shift(mix_seed(hash, 2))).fract(),
+        )
+    }
+}
+
+const MASK_SIZE: usize = 64;
+
+/// A tileable blue noise mask with values in (0, 1), made the first time
+/// it's needed by a simple void-and-cluster method: points are added one at
+/// a time where they are furthest from all the others, and each pixel's value
+/// is when it was picked.
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        const N: usize = MASK_SIZE * MASK_SIZE;
+        let sigma: f64 = 1.5;
+
+        // Gaussian of the distance, wrapping around the edges.
+        let mut kernel = vec![0.0; N];
+        for dy in 0..MASK_SIZE {
+            for dx in 0..MASK_SIZE {
+                let x = dx.min(MASK_SIZE - dx) as f64;
+                let y = dy.min(MASK_SIZE - dy) as f64;
+                kernel[dy * MASK_SIZE + dx] = (-(x * x + y * y) / (2.0 * sigma * sigma)).exp();
+            }
+        }
+
+        let mut energy = vec![0.0; N];
+        let mut mask = vec![f64::NAN; N];
+        for rank in 0..N {
+            let mut void = 0;
+            let mut lowest = f64::INFINITY;
+            for (i, e) in energy.iter().enumerate() {
+                if mask[i].is_nan() && *e < lowest {
+                    lowest = *e;
+                    void = i;
+                }
+            }
+            mask[void] = (rank as f64 + 0.5) / N as f64;
+
+            let (vx, vy) = (void % MASK_SIZE, void / MASK_SIZE);
+            for y in 0..MASK_SIZE {
+                let dy = (y + MASK_SIZE - vy) % MASK_SIZE;
+                for x in 0..MASK_SIZE {
+                    let dx = (x + MASK_SIZE - vx) % MASK_SIZE;
+                    energy[y * MASK_SIZE + x] += kernel[dy * MASK_SIZE + dx];
+                }
+            }
+        }
+        mask
+    })
+}
+
+/// Owen scrambling of the bits of `x`, from the most significant.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Scrambles the bits of `x` so each only depends on the ones below it.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+/// A permutation of 0..n picked by `seed`, without building it. From
+/// Kensler, "Correlated Multi-Jittered Sampling" (2013).
+fn permute(mut i: u32, n: u32, seed: u32) -> u32 {
+    let p = seed;
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % n
+}
+
+fn to_unit(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn to_unit_u32(x: u32) -> f64 {
+    x as f64 / (1u64 << 32) as f64
+}
+
+#[test]
+fn test_samplers_cover_the_square() {
+    // With 16 samples, every sampler except the independent one puts one
+    // in each cell of a 4x4 grid, in the first dimensions and later ones.
+    for kind in &SamplerKind::ALL[1..] {
+        let mut sampler = kind.create(7, 16);
+        for dimension in 0..3 {
+            let mut cells = [false; 16];
+            for index in 0..16 {
+                sampler.start_pixel_sample(3, 5, index);
+                for _ in 0..dimension {
+                    sampler.get_2d();
+                }
+                let (u, v) = sampler.get_2d();
+                assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                cells[(v * 4.0) as usize * 4 + (u * 4.0) as usize] = true;
+            }
+            // Rotated points only fall in a shifted grid, but still spread out.
+            let filled = cells.iter().filter(|c| **c).count();
+            let expected = match kind {
+                SamplerKind::Halton | SamplerKind::BlueNoise => 10,
+                _ => 16,
+            };
+            assert!(filled >= expected, "{} {} {}", kind, dimension, filled);
+        }
+    }
+
+    // The same sample always gets the same numbers, other pixels different
+    // ones.
+    for kind in &SamplerKind::ALL {
+        let mut sampler = kind.create(7, 16);
+        let mut numbers = |x: u32, index: u32| {
+            sampler.start_pixel_sample(x, 0, index);
+            (sampler.get_2d(), sampler.get_1d())
+        };
+        let first = numbers(0, 3);
+        numbers(0, 4);
+        assert_eq!(numbers(0, 3), first, "{}", kind);
+        assert_ne!(numbers(1, 3), first, "{}", kind);
+    }
+
+    assert_eq!("blue-noise".parse(), Ok(SamplerKind::BlueNoise));
+    assert!("best".parse::<SamplerKind>().is_err());
+}
+
+#[test]
+fn test_blue_noise_mask() {
+    let mask = blue_noise_mask();
+    let mut sorted = mask.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert!(sorted
+        .iter()
+        .enumerate()
+        .all(|(i, v)| *v == (i as f64 + 0.5) / 4096.0));
+
+    // Neighbors differ more than they would in white noise, where the
+    // average difference is 1/3.
+    let mut total = 0.0;
+    for y in 0..MASK_SIZE {
+        for x in 0..MASK_SIZE {
+            total += (mask[y * MASK_SIZE + x] - mask[y * MASK_SIZE + (x + 1) % MASK_SIZE]).abs();
+        }
+    }
+    let average = total / (MASK_SIZE * MASK_SIZE) as f64;
+    assert!(average > 0.4, "{}", average);
+}
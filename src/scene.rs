@@ -0,0 +1,1451 @@
+//! Loading scenes from TOML files.
+//!
+//! A scene file looks like this:
+//!
+//! ```toml
+//! [render]
+//! image_width = 400
+//! aspect_ratio = 1.7777777777777777
+//! samples_per_pixel = 100
+//! max_depth = 50
+//! # "sky" for the blue gradient (the default) or a color.
+//! background = [0.0, 0.0, 0.0]
+//! # Trace wavelengths instead of RGB, so dispersive materials split light
+//! # into colors. Slower to converge, off by default.
+//! spectral = true
+//! # How the image is turned into 8-bit colors: exposure in stops, a tone
+//! # mapping operator ("clamp", the default, "reinhard", "aces" or "agx")
+//! # and whether to dither.
+//! exposure = 0.5
+//! tonemap = "agx"
+//! dither = true
+//!
+//! [camera]
+//! lookfrom = [13.0, 2.0, 3.0]
+//! lookat = [0.0, 0.0, 0.0]
+//! vfov = 20.0
+//! aperture = 0.1
+//! focus_dist = 10.0
+//! # The shutter is open from `shutter_open` to `shutter_close`, objects that
+//! # move do so between time 0 and 1. By default the shutter opens and closes
+//! # at time 0, so there is no motion blur.
+//! shutter_open = 0.0
+//! shutter_close = 1.0
+//!
+//! [textures.checker]
+//! type = "checker"
+//! odd = [0.2, 0.3, 0.1]
+//! even = [0.9, 0.9, 0.9]
+//! scale = 0.5
+//!
+//! [textures.earth]
+//! type = "image"
+//! path = "earthmap.png"
+//!
+//! # Procedural textures: "noise", "marble", "wood" and "clouds". All take an
+//! # optional `seed` and `scale`, see `TextureDesc` for their colors.
+//! [textures.marble]
+//! type = "marble"
+//! seed = 7
+//! scale = 4.0
+//!
+//! # Colors can be given as a texture name or directly as an RGB triple.
+//! [materials.ground]
+//! type = "lambertian"
+//! albedo = "checker"
+//!
+//! [materials.red]
+//! type = "lambertian"
+//! albedo = [0.8, 0.1, 0.1]
+//!
+//! # Physically based: GGX highlights over a diffuse base, or a tinted
+//! # reflection only when `metallic` is 1.
+//! [materials.gold]
+//! type = "microfacet"
+//! base_color = [1.0, 0.78, 0.34]
+//! roughness = 0.3
+//! metallic = 1.0
+//!
+//! # Glass whose index of refraction depends on the wavelength. Give either
+//! # a known `glass` ("bk7" or "sf11"), `cauchy = [a, b]` or
+//! # `sellmeier = { b = [...], c = [...] }`, with wavelengths in micrometres.
+//! [materials.prism]
+//! type = "dispersive"
+//! glass = "sf11"
+//!
+//! [materials.mist]
+//! type = "isotropic"
+//! albedo = [1.0, 1.0, 1.0]
+//!
+//! [[spheres]]
+//! center = [0.0, -1000.0, 0.0]
+//! radius = 1000.0
+//! material = "ground"
+//!
+//! # A sphere moving from `center` at time 0 to `center1` at time 1.
+//! [[spheres]]
+//! center = [4.0, 1.0, 0.0]
+//! center1 = [4.0, 1.5, 0.0]
+//! radius = 1.0
+//! material = "red"
+//!
+//! # Filled with fog of the given density instead of having a surface, boxes
+//! # can be filled too. The material scatters light inside, usually
+//! # "isotropic" (which takes an `albedo`).
+//! [[spheres]]
+//! center = [0.0, 0.0, 0.0]
+//! radius = 5000.0
+//! material = "mist"
+//! density = 0.0001
+//!
+//! [[triangles]]
+//! vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+//! # Optional, per vertex.
+//! normals = [[0.0, 0.0, 1.0], [0.0, 0.0, 1.0], [0.0, 0.0, 1.0]]
+//! uvs = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]
+//! material = "ground"
+//!
+//! # A parallelogram with a corner at `q` and sides `u` and `v`, facing the
+//! # side `u x v` points to.
+//! [[quads]]
+//! q = [-1.0, 0.0, -1.0]
+//! u = [2.0, 0.0, 0.0]
+//! v = [0.0, 2.0, 0.0]
+//! material = "red"
+//!
+//! # A box between two corners. It can be placed with `scale`, `rotate` and
+//! # `translate` like models below.
+//! [[boxes]]
+//! min = [0.0, 0.0, 0.0]
+//! max = [1.0, 2.0, 1.0]
+//! rotate = [0.0, 15.0, 0.0]
+//! material = "red"
+//!
+//! # Wavefront OBJ file, materials come from its MTL files. The material
+//! # given here is used for faces that don't have one.
+//! [[models]]
+//! path = "teapot.obj"
+//! material = "ground"
+//! # Optional placement: scaled (by a number or per axis), then rotated by
+//! # degrees around the x, y and z axes in that order, then translated.
+//! scale = 0.5
+//! rotate = [0.0, 45.0, 0.0]
+//! translate = [0.0, 0.0, -2.0]
+//! # Optional, how far the model moves between time 0 and 1.
+//! motion = [0.0, 0.0, 0.5]
+//!
+//! # The same file with the same material is only loaded once, so a model can
+//! # be placed many times without using more memory.
+//! [[models]]
+//! path = "teapot.obj"
+//! material = "ground"
+//! translate = [2.0, 0.0, -2.0]
+//! ```
+//!
+//! Spheres, triangles and quads with a `diffuse_light` material are sampled
+//! directly as lights, which cuts down noise a lot when the lights are small.
+//!
+//! Textures and materials are declared once by name and materials and shapes
+//! refer to them, so many shapes can share a single material.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::camera::Camera;
+use crate::materials::dielectric::Dielectric;
+use crate::materials::diffuse_light::DiffuseLight;
+use crate::materials::dispersive::{Dispersion, Dispersive};
+use crate::materials::isotropic::Isotropic;
+use crate::materials::lambertian::Lambertian;
+use crate::materials::metal::Metal;
+use crate::materials::microfacet::Microfacet;
+use crate::materials::Material;
+use crate::obj::load_obj;
+use crate::render::Background;
+use crate::shapes::box_shape::BoxShape;
+use crate::shapes::bvh::BvhNode;
+use crate::shapes::constant_medium::ConstantMedium;
+use crate::shapes::hittable::Hittable;
+use crate::shapes::hittable_list::HittableList;
+use crate::shapes::moving::Moving;
+use crate::shapes::moving_sphere::MovingSphere;
+use crate::shapes::quad::Quad;
+use crate::shapes::sphere::Sphere;
+use crate::shapes::transformed::Transformed;
+use crate::shapes::triangle::Triangle;
+use crate::spectrum::{LAMBDA_D, LAMBDA_MAX, LAMBDA_MIN};
+use crate::textures::checker::CheckerTexture;
+use crate::textures::image_texture::ImageTexture;
+use crate::textures::noise::{CloudTexture, MarbleTexture, NoiseTexture, WoodTexture};
+use crate::textures::solid_color::SolidColor;
+use crate::textures::Texture;
+use crate::tonemap::ToneMap;
+use crate::transform::Transform;
+use crate::vec3::{Point3, Vec3};
+
+/// How the image should be rendered, as opposed to what is in it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub image_width: i32,
+    pub image_height: i32,
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+    pub background: Background,
+    /// Trace wavelengths instead of RGB, needed for dispersion.
+    pub spectral: bool,
+    pub tone_map: ToneMap,
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f64 {
+        self.image_width as f64 / self.image_height as f64
+    }
+}
+
+/// Where the camera is and how it is set up. The `Camera` itself is only
+/// built once the final image size is known, so these can still be changed
+/// after the scene is loaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraSettings {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    /// Vertical field of view in degrees.
+    pub vfov: f64,
+    pub aperture: f64,
+    /// Distance to the plane in focus, `lookat` is in focus without it.
+    pub focus_dist: Option<f64>,
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+impl CameraSettings {
+    pub fn camera(&self, aspect_ratio: f64) -> Camera {
+        let focus_dist = self
+            .focus_dist
+            .unwrap_or_else(|| (self.lookfrom - self.lookat).length());
+
+        Camera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            focus_dist,
+            self.shutter_open,
+            self.shutter_close,
+        )
+    }
+}
+
+pub struct Scene {
+    pub camera: CameraSettings,
+    pub world: HittableList,
+    /// Objects that give off light and can be sampled directly. They are in
+    /// `world` as well.
+    pub lights: HittableList,
+    pub settings: RenderSettings,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    /// The file isn't valid TOML or doesn't match the expected layout. The
+    /// message from the parser already includes the line and column.
+    Parse(String),
+    /// The file parsed but a value in it doesn't make sense.
+    Invalid {
+        line: Option<usize>,
+        field: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "could not read scene file: {}", e),
+            SceneError::Parse(message) => write!(f, "{}", message.trim_end()),
+            SceneError::Invalid {
+                line: Some(line),
+                field,
+                message,
+            } => write!(f, "line {}: {}: {}", line, field, message),
+            SceneError::Invalid {
+                line: None,
+                field,
+                message,
+            } => write!(f, "{}: {}", field, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(e: std::io::Error) -> SceneError {
+        SceneError::Io(e)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    render: RenderDesc,
+    camera: CameraDesc,
+    #[serde(default)]
+    textures: BTreeMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MaterialDesc>>,
+    #[serde(default)]
+    spheres: Vec<SphereDesc>,
+    #[serde(default)]
+    triangles: Vec<TriangleDesc>,
+    #[serde(default)]
+    quads: Vec<QuadDesc>,
+    #[serde(default)]
+    boxes: Vec<BoxDesc>,
+    #[serde(default)]
+    models: Vec<ModelDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenderDesc {
+    image_width: Spanned<i32>,
+    image_height: Option<Spanned<i32>>,
+    aspect_ratio: Option<Spanned<f64>>,
+    samples_per_pixel: Spanned<i32>,
+    max_depth: Spanned<i32>,
+    #[serde(default)]
+    background: BackgroundDesc,
+    #[serde(default)]
+    spectral: bool,
+    exposure: Option<Spanned<f64>>,
+    tonemap: Option<Spanned<String>>,
+    #[serde(default)]
+    dither: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BackgroundDesc {
+    Solid([f64; 3]),
+    Named(Spanned<String>),
+}
+
+impl Default for BackgroundDesc {
+    fn default() -> BackgroundDesc {
+        BackgroundDesc::Named(Spanned::new(0..0, "sky".to_string()))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    lookfrom: [f64; 3],
+    lookat: Spanned<[f64; 3]>,
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    vfov: Spanned<f64>,
+    #[serde(default)]
+    aperture: f64,
+    focus_dist: Option<f64>,
+    #[serde(default)]
+    shutter_open: f64,
+    shutter_close: Option<Spanned<f64>>,
+}
+
+fn default_vup() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+/// Anywhere a texture can be used a plain color can be given instead.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorOrTexture {
+    Color([f64; 3]),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        odd: ColorOrTexture,
+        even: ColorOrTexture,
+        #[serde(default = "default_checker_scale")]
+        scale: f64,
+    },
+    Image {
+        path: String,
+    },
+    Noise {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_scale")]
+        scale: f64,
+    },
+    Marble {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_scale")]
+        scale: f64,
+        #[serde(default = "default_white")]
+        color: [f64; 3],
+    },
+    Wood {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_scale")]
+        scale: f64,
+        #[serde(default = "default_light_wood")]
+        light: [f64; 3],
+        #[serde(default = "default_dark_wood")]
+        dark: [f64; 3],
+    },
+    Clouds {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_scale")]
+        scale: f64,
+        #[serde(default = "default_sky")]
+        sky: [f64; 3],
+        #[serde(default = "default_white")]
+        cloud: [f64; 3],
+    },
+}
+
+fn default_checker_scale() -> f64 {
+    1.0
+}
+
+fn default_scale() -> f64 {
+    4.0
+}
+
+fn default_white() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn default_light_wood() -> [f64; 3] {
+    [0.75, 0.52, 0.3]
+}
+
+fn default_dark_wood() -> [f64; 3] {
+    [0.4, 0.22, 0.1]
+}
+
+fn default_sky() -> [f64; 3] {
+    [0.3, 0.5, 0.9]
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: ColorOrTexture,
+    },
+    Metal {
+        albedo: ColorOrTexture,
+        fuzz: f64,
+    },
+    Dielectric {
+        ref_idx: f64,
+    },
+    Dispersive {
+        glass: Option<String>,
+        cauchy: Option<[f64; 2]>,
+        sellmeier: Option<SellmeierDesc>,
+    },
+    DiffuseLight {
+        emit: ColorOrTexture,
+    },
+    Isotropic {
+        albedo: ColorOrTexture,
+    },
+    Microfacet {
+        base_color: ColorOrTexture,
+        roughness: f64,
+        #[serde(default)]
+        metallic: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SellmeierDesc {
+    b: [f64; 3],
+    c: [f64; 3],
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereDesc {
+    center: [f64; 3],
+    center1: Option<[f64; 3]>,
+    radius: Spanned<f64>,
+    material: Option<Spanned<String>>,
+    density: Option<Spanned<f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriangleDesc {
+    vertices: Spanned<[[f64; 3]; 3]>,
+    normals: Option<Spanned<[[f64; 3]; 3]>>,
+    uvs: Option<[[f64; 2]; 3]>,
+    material: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuadDesc {
+    q: [f64; 3],
+    u: [f64; 3],
+    v: Spanned<[f64; 3]>,
+    material: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoxDesc {
+    min: [f64; 3],
+    max: [f64; 3],
+    material: Option<Spanned<String>>,
+    density: Option<Spanned<f64>>,
+    scale: Option<Spanned<ScaleDesc>>,
+    rotate: Option<[f64; 3]>,
+    translate: Option<[f64; 3]>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelDesc {
+    path: Spanned<String>,
+    material: Option<Spanned<String>>,
+    scale: Option<Spanned<ScaleDesc>>,
+    rotate: Option<[f64; 3]>,
+    translate: Option<[f64; 3]>,
+    motion: Option<[f64; 3]>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    PerAxis([f64; 3]),
+}
+
+fn vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+/// Turns a byte offset into the source into a 1-based line number.
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+/// The line `key` is on in the table at `span`. Values inside tagged tables
+/// can't be `Spanned`, so the key is looked for in the source instead. Keys
+/// that aren't written out, left at their default, give the table's line.
+fn key_line(source: &str, span: Range<usize>, key: &str) -> usize {
+    let table = &source[span.start.min(source.len())..span.end.min(source.len())];
+    let found = table.match_indices(key).find(|(i, _)| {
+        let starts_key = match table[..*i].chars().next_back() {
+            Some(c) => c.is_whitespace() || c == '{' || c == ',',
+            None => true,
+        };
+        starts_key && table[i + key.len()..].trim_start().starts_with('=')
+    });
+    line_of(source, span.start + found.map_or(0, |(i, _)| i))
+}
+
+fn invalid<T>(
+    source: &str,
+    spanned: Option<&Spanned<T>>,
+    field: &str,
+    message: &str,
+) -> SceneError {
+    SceneError::Invalid {
+        line: spanned.map(|s| line_of(source, s.span().start)),
+        field: field.to_string(),
+        message: message.to_string(),
+    }
+}
+
+impl Scene {
+    /// Loads a scene file. Models are looked up relative to the directory the
+    /// scene file is in.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        Scene::parse(&source, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Models are looked up relative to the current directory.
+    pub fn from_toml(source: &str) -> Result<Scene, SceneError> {
+        Scene::parse(source, Path::new(""))
+    }
+
+    fn parse(source: &str, base: &Path) -> Result<Scene, SceneError> {
+        let desc: SceneDesc =
+            toml::from_str(source).map_err(|e| SceneError::Parse(e.to_string()))?;
+
+        let settings = build_settings(source, &desc.render)?;
+        let camera = build_camera(source, &desc.camera)?;
+
+        let mut textures = Textures {
+            source,
+            descs: &desc.textures,
+            built: BTreeMap::new(),
+            base,
+        };
+        for (name, t) in &desc.textures {
+            let line = line_of(source, t.span().start);
+            textures.get(name, &format!("textures.{}", name), line, &mut Vec::new())?;
+        }
+
+        let mut materials: BTreeMap<&str, Arc<dyn Material + Sync + Send>> = BTreeMap::new();
+        for (name, m) in &desc.materials {
+            materials.insert(name, build_material(source, name, m, &mut textures)?);
+        }
+
+        let lookup = |name: &Option<Spanned<String>>, field: &str| match name {
+            Some(name) => match materials.get(name.get_ref().as_str()) {
+                Some(m) => Ok(Some(m.clone())),
+                None => Err(invalid(
+                    source,
+                    Some(name),
+                    field,
+                    &format!("unknown material `{}`", name.get_ref()),
+                )),
+            },
+            None => Ok(None),
+        };
+
+        // Shapes with a density are filled with fog instead of having a
+        // surface, their material is used as the phase function.
+        let fill = |shape: Box<dyn Hittable + Send + Sync>,
+                    density: &Option<Spanned<f64>>,
+                    mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+                    field: &str|
+         -> Result<Box<dyn Hittable + Send + Sync>, SceneError> {
+            let density = match density {
+                Some(density) => density,
+                None => return Ok(shape),
+            };
+            if *density.get_ref() <= 0.0 {
+                return Err(invalid(
+                    source,
+                    Some(density),
+                    &format!("{}.density", field),
+                    "must be greater than zero",
+                ));
+            }
+            match mat_ptr {
+                Some(phase_function) => Ok(Box::new(ConstantMedium::new(
+                    shape,
+                    *density.get_ref(),
+                    phase_function,
+                ))),
+                None => Err(invalid(
+                    source,
+                    Some(density),
+                    &format!("{}.material", field),
+                    "is needed to fill the shape",
+                )),
+            }
+        };
+
+        // Shapes made of a light material are also sampled directly by the
+        // renderer.
+        let is_light = |name: &Option<Spanned<String>>| match name {
+            Some(name) => matches!(
+                desc.materials.get(name.get_ref()).map(Spanned::get_ref),
+                Some(MaterialDesc::DiffuseLight { .. })
+            ),
+            None => false,
+        };
+
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+        for (i, s) in desc.spheres.iter().enumerate() {
+            if *s.radius.get_ref() == 0.0 {
+                return Err(invalid(
+                    source,
+                    Some(&s.radius),
+                    &format!("spheres[{}].radius", i),
+                    "must not be zero",
+                ));
+            }
+
+            let mat_ptr = lookup(&s.material, &format!("spheres[{}].material", i))?;
+            let sphere: Box<dyn Hittable + Send + Sync> = match s.center1 {
+                Some(center1) => Box::new(MovingSphere::new(
+                    vec3(s.center),
+                    vec3(center1),
+                    0.0,
+                    1.0,
+                    *s.radius.get_ref(),
+                    mat_ptr.clone(),
+                )),
+                None if is_light(&s.material) && s.density.is_none() => {
+                    let sphere = Arc::new(Sphere::new(
+                        vec3(s.center),
+                        *s.radius.get_ref(),
+                        mat_ptr.clone(),
+                    ));
+                    lights.add(Box::new(sphere.clone()));
+                    Box::new(sphere)
+                }
+                None => Box::new(Sphere::new(
+                    vec3(s.center),
+                    *s.radius.get_ref(),
+                    mat_ptr.clone(),
+                )),
+            };
+            world.add(fill(
+                sphere,
+                &s.density,
+                mat_ptr,
+                &format!("spheres[{}]", i),
+            )?);
+        }
+
+        for (i, t) in desc.triangles.iter().enumerate() {
+            let [v0, v1, v2] = t.vertices.get_ref().map(vec3);
+            if (v1 - v0).cross(&(v2 - v0)).length_squared() == 0.0 {
+                return Err(invalid(
+                    source,
+                    Some(&t.vertices),
+                    &format!("triangles[{}].vertices", i),
+                    "must not be in a line",
+                ));
+            }
+            if let Some(normals) = &t.normals {
+                if normals.get_ref().contains(&[0.0; 3]) {
+                    return Err(invalid(
+                        source,
+                        Some(normals),
+                        &format!("triangles[{}].normals", i),
+                        "must not be zero",
+                    ));
+                }
+            }
+
+            let mat_ptr = lookup(&t.material, &format!("triangles[{}].material", i))?;
+            let mut triangle = Triangle::new(v0, v1, v2, mat_ptr);
+            if let Some(normals) = &t.normals {
+                triangle = triangle.with_normals(normals.get_ref().map(|n| vec3(n).unit()));
+            }
+            if let Some(uvs) = t.uvs {
+                triangle = triangle.with_uvs(uvs.map(|uv| (uv[0], uv[1])));
+            }
+            if is_light(&t.material) {
+                let triangle = Arc::new(triangle);
+                lights.add(Box::new(triangle.clone()));
+                world.add(Box::new(triangle));
+            } else {
+                world.add(Box::new(triangle));
+            }
+        }
+
+        for (i, quad) in desc.quads.iter().enumerate() {
+            let (u, v) = (vec3(quad.u), vec3(*quad.v.get_ref()));
+            if u.cross(&v).length_squared() == 0.0 {
+                return Err(invalid(
+                    source,
+                    Some(&quad.v),
+                    &format!("quads[{}].v", i),
+                    "must not be parallel to `u`",
+                ));
+            }
+
+            let mat_ptr = lookup(&quad.material, &format!("quads[{}].material", i))?;
+            let quad_shape = Quad::new(vec3(quad.q), u, v, mat_ptr);
+            if is_light(&quad.material) {
+                let quad_shape = Arc::new(quad_shape);
+                lights.add(Box::new(quad_shape.clone()));
+                world.add(Box::new(quad_shape));
+            } else {
+                world.add(Box::new(quad_shape));
+            }
+        }
+
+        for (i, b) in desc.boxes.iter().enumerate() {
+            let mat_ptr = lookup(&b.material, &format!("boxes[{}].material", i))?;
+            let transform = build_transform(
+                source,
+                &b.scale,
+                b.rotate,
+                b.translate,
+                &format!("boxes[{}]", i),
+            )?;
+            let shape = BoxShape::new(vec3(b.min), vec3(b.max), mat_ptr.clone());
+            let placed = place(Arc::new(shape), transform);
+            world.add(fill(placed, &b.density, mat_ptr, &format!("boxes[{}]", i))?);
+        }
+
+        let mut models: BTreeMap<(&str, Option<&str>), Arc<dyn Hittable + Send + Sync>> =
+            BTreeMap::new();
+        for (i, m) in desc.models.iter().enumerate() {
+            let key = (
+                m.path.get_ref().as_str(),
+                m.material.as_ref().map(|name| name.get_ref().as_str()),
+            );
+            let model = match models.get(&key) {
+                Some(model) => model.clone(),
+                None => {
+                    let field = format!("models[{}].path", i);
+                    let default_material = lookup(&m.material, &format!("models[{}].material", i))?;
+                    let obj = load_obj(base.join(m.path.get_ref()), default_material)
+                        .map_err(|e| invalid(source, Some(&m.path), &field, &e.to_string()))?;
+                    if obj.meshes.is_empty() {
+                        return Err(invalid(source, Some(&m.path), &field, "has no faces"));
+                    }
+
+                    let model: Arc<dyn Hittable + Send + Sync> =
+                        Arc::new(BvhNode::new(obj.into_list(), 0.0, 0.0));
+                    models.insert(key, model.clone());
+                    model
+                }
+            };
+
+            let transform = build_transform(
+                source,
+                &m.scale,
+                m.rotate,
+                m.translate,
+                &format!("models[{}]", i),
+            )?;
+            let mut object = place(model, transform);
+            if let Some(motion) = m.motion {
+                object = Box::new(Moving::new(object, Vec3::zero(), vec3(motion), 0.0, 1.0));
+            }
+            world.add(object);
+        }
+
+        Ok(Scene {
+            camera,
+            world,
+            lights,
+            settings,
+        })
+    }
+}
+
+fn build_settings(source: &str, r: &RenderDesc) -> Result<RenderSettings, SceneError> {
+    let positive = |value: &Spanned<i32>, field: &str| {
+        if *value.get_ref() > 0 {
+            Ok(*value.get_ref())
+        } else {
+            Err(invalid(source, Some(value), field, "must be greater than zero"))
+        }
+    };
+
+    let image_width = positive(&r.image_width, "render.image_width")?;
+    let samples_per_pixel = positive(&r.samples_per_pixel, "render.samples_per_pixel")?;
+    let max_depth = positive(&r.max_depth, "render.max_depth")?;
+
+    let image_height = match (&r.image_height, &r.aspect_ratio) {
+        (Some(h), None) => positive(h, "render.image_height")?,
+        (None, Some(a)) => {
+            if *a.get_ref() <= 0.0 {
+                return Err(invalid(
+                    source,
+                    Some(a),
+                    "render.aspect_ratio",
+                    "must be greater than zero",
+                ));
+            }
+            ((image_width as f64 / a.get_ref()) as i32).max(1)
+        }
+        (None, None) => ((image_width as f64 / (16.0 / 9.0)) as i32).max(1),
+        (Some(h), Some(_)) => {
+            return Err(invalid(
+                source,
+                Some(h),
+                "render.image_height",
+                "only one of `image_height` and `aspect_ratio` may be given",
+            ))
+        }
+    };
+
+    let background = match &r.background {
+        BackgroundDesc::Solid(color) => Background::Solid(vec3(*color)),
+        BackgroundDesc::Named(name) if name.get_ref() == "sky" => Background::Sky,
+        BackgroundDesc::Named(name) => {
+            return Err(invalid(
+                source,
+                Some(name),
+                "render.background",
+                "must be \"sky\" or a color",
+            ))
+        }
+    };
+
+    let mut tone_map = ToneMap {
+        dither: r.dither,
+        ..ToneMap::default()
+    };
+    if let Some(exposure) = &r.exposure {
+        if !exposure.get_ref().is_finite() {
+            return Err(invalid(
+                source,
+                Some(exposure),
+                "render.exposure",
+                "must be finite",
+            ));
+        }
+        tone_map.exposure = *exposure.get_ref();
+    }
+    if let Some(name) = &r.tonemap {
+        tone_map.operator = name
+            .get_ref()
+            .parse()
+            .map_err(|e: String| invalid(source, Some(name), "render.tonemap", &e))?;
+    }
+
+    Ok(RenderSettings {
+        image_width,
+        image_height,
+        samples_per_pixel,
+        max_depth,
+        background,
+        spectral: r.spectral,
+        tone_map,
+    })
+}
+
+/// Scales, then rotates, then translates. `field` is the object the transform
+/// belongs to, for errors.
+fn build_transform(
+    source: &str,
+    scale: &Option<Spanned<ScaleDesc>>,
+    rotate: Option<[f64; 3]>,
+    translate: Option<[f64; 3]>,
+    field: &str,
+) -> Result<Transform, SceneError> {
+    let mut transform = Transform::identity();
+
+    if let Some(scale) = scale {
+        let factors = match scale.get_ref() {
+            ScaleDesc::Uniform(s) => Vec3::new(*s, *s, *s),
+            ScaleDesc::PerAxis(s) => vec3(*s),
+        };
+        if factors.x == 0.0 || factors.y == 0.0 || factors.z == 0.0 {
+            return Err(invalid(
+                source,
+                Some(scale),
+                &format!("{}.scale", field),
+                "must not be zero",
+            ));
+        }
+        transform = Transform::scale(factors);
+    }
+    if let Some([x, y, z]) = rotate {
+        transform = transform
+            .then(&Transform::rotate_x(x))
+            .then(&Transform::rotate_y(y))
+            .then(&Transform::rotate_z(z));
+    }
+    if let Some(offset) = translate {
+        transform = transform.then(&Transform::translate(vec3(offset)));
+    }
+
+    Ok(transform)
+}
+
+/// Only wraps the object in a `Transformed` if it actually moves.
+fn place(
+    object: Arc<dyn Hittable + Send + Sync>,
+    transform: Transform,
+) -> Box<dyn Hittable + Send + Sync> {
+    if transform == Transform::identity() {
+        Box::new(object)
+    } else {
+        Box::new(Transformed::new(object, transform))
+    }
+}
+
+fn build_camera(source: &str, c: &CameraDesc) -> Result<CameraSettings, SceneError> {
+    let vfov = *c.vfov.get_ref();
+    if vfov <= 0.0 || vfov >= 180.0 {
+        return Err(invalid(
+            source,
+            Some(&c.vfov),
+            "camera.vfov",
+            "must be between 0 and 180 degrees",
+        ));
+    }
+
+    let lookfrom = vec3(c.lookfrom);
+    let lookat = vec3(*c.lookat.get_ref());
+    if lookfrom == lookat {
+        return Err(invalid(
+            source,
+            Some(&c.lookat),
+            "camera.lookat",
+            "must be different from `lookfrom`",
+        ));
+    }
+
+    let shutter_close = match &c.shutter_close {
+        Some(close) if *close.get_ref() < c.shutter_open => {
+            return Err(invalid(
+                source,
+                Some(close),
+                "camera.shutter_close",
+                "must not be before `shutter_open`",
+            ))
+        }
+        Some(close) => *close.get_ref(),
+        None => c.shutter_open,
+    };
+
+    Ok(CameraSettings {
+        lookfrom,
+        lookat,
+        vup: vec3(c.vup),
+        vfov,
+        aperture: c.aperture,
+        focus_dist: c.focus_dist,
+        shutter_open: c.shutter_open,
+        shutter_close,
+    })
+}
+
+/// Builds textures on demand so they can refer to each other in any order.
+struct Textures<'a> {
+    source: &'a str,
+    descs: &'a BTreeMap<String, Spanned<TextureDesc>>,
+    built: BTreeMap<String, Arc<dyn Texture + Sync + Send>>,
+    base: &'a Path,
+}
+
+impl<'a> Textures<'a> {
+    /// `field` and `line` are where the texture is used. `stack` holds the
+    /// textures currently being built, to catch a texture that (indirectly)
+    /// contains itself.
+    fn get(
+        &mut self,
+        name: &str,
+        field: &str,
+        line: usize,
+        stack: &mut Vec<String>,
+    ) -> Result<Arc<dyn Texture + Sync + Send>, SceneError> {
+        if let Some(t) = self.built.get(name) {
+            return Ok(t.clone());
+        }
+
+        let error = |message: String| SceneError::Invalid {
+            line: Some(line),
+            field: field.to_string(),
+            message,
+        };
+
+        let desc = match self.descs.get(name) {
+            Some(desc) => desc,
+            None => return Err(error(format!("unknown texture `{}`", name))),
+        };
+        if stack.iter().any(|n| n == name) {
+            return Err(error(format!("texture `{}` contains itself", name)));
+        }
+        stack.push(name.to_string());
+
+        let source = self.source;
+        let span = desc.span();
+        let key = |key: &str| {
+            (
+                format!("textures.{}.{}", name, key),
+                key_line(source, span.clone(), key),
+            )
+        };
+        let scale_error = || {
+            let (field, line) = key("scale");
+            SceneError::Invalid {
+                line: Some(line),
+                field,
+                message: "must be greater than zero".to_string(),
+            }
+        };
+
+        let texture: Arc<dyn Texture + Sync + Send> = match desc.get_ref() {
+            TextureDesc::Solid { color } => Arc::new(SolidColor::new(vec3(*color))),
+            TextureDesc::Checker { odd, even, scale } => {
+                if *scale <= 0.0 {
+                    return Err(scale_error());
+                }
+                let (odd_field, odd_line) = key("odd");
+                let (even_field, even_line) = key("even");
+                Arc::new(CheckerTexture {
+                    odd: self.resolve(odd, &odd_field, odd_line, stack)?,
+                    even: self.resolve(even, &even_field, even_line, stack)?,
+                    scale: *scale,
+                })
+            }
+            TextureDesc::Image { path } => match ImageTexture::load(self.base.join(path)) {
+                Ok(t) => Arc::new(t),
+                Err(e) => {
+                    let (field, line) = key("path");
+                    return Err(SceneError::Invalid {
+                        line: Some(line),
+                        field,
+                        message: format!("could not load `{}`: {}", path, e),
+                    });
+                }
+            },
+            TextureDesc::Noise { scale, .. }
+            | TextureDesc::Marble { scale, .. }
+            | TextureDesc::Wood { scale, .. }
+            | TextureDesc::Clouds { scale, .. }
+                if *scale <= 0.0 =>
+            {
+                return Err(scale_error());
+            }
+            TextureDesc::Noise { seed, scale } => Arc::new(NoiseTexture::new(*seed, *scale)),
+            TextureDesc::Marble { seed, scale, color } => {
+                Arc::new(MarbleTexture::new(*seed, *scale, vec3(*color)))
+            }
+            TextureDesc::Wood {
+                seed,
+                scale,
+                light,
+                dark,
+            } => Arc::new(WoodTexture::new(*seed, *scale, vec3(*light), vec3(*dark))),
+            TextureDesc::Clouds {
+                seed,
+                scale,
+                sky,
+                cloud,
+            } => Arc::new(CloudTexture::new(*seed, *scale, vec3(*sky), vec3(*cloud))),
+        };
+
+        stack.pop();
+        self.built.insert(name.to_string(), texture.clone());
+        Ok(texture)
+    }
+
+    fn resolve(
+        &mut self,
+        value: &ColorOrTexture,
+        field: &str,
+        line: usize,
+        stack: &mut Vec<String>,
+    ) -> Result<Arc<dyn Texture + Sync + Send>, SceneError> {
+        match value {
+            ColorOrTexture::Color(color) => Ok(Arc::new(SolidColor::new(vec3(*color)))),
+            ColorOrTexture::Texture(name) => self.get(name, field, line, stack),
+        }
+    }
+}
+
+fn build_material(
+    source: &str,
+    name: &str,
+    m: &Spanned<MaterialDesc>,
+    textures: &mut Textures,
+) -> Result<Arc<dyn Material + Sync + Send>, SceneError> {
+    let error = |field: &str, message: String| SceneError::Invalid {
+        line: Some(key_line(source, m.span(), field)),
+        field: format!("materials.{}.{}", name, field),
+        message,
+    };
+    let mut texture = |value: &ColorOrTexture, field: &str| {
+        textures.resolve(
+            value,
+            &format!("materials.{}.{}", name, field),
+            key_line(source, m.span(), field),
+            &mut Vec::new(),
+        )
+    };
+
+    let material: Arc<dyn Material + Sync + Send> = match m.get_ref() {
+        MaterialDesc::Lambertian { albedo } => {
+            Arc::new(Lambertian::from_texture(texture(albedo, "albedo")?))
+        }
+        MaterialDesc::Metal { albedo, fuzz } => {
+            if !(0.0..=1.0).contains(fuzz) {
+                return Err(error("fuzz", "must be between 0 and 1".to_string()));
+            }
+            Arc::new(Metal::from_texture(texture(albedo, "albedo")?, *fuzz))
+        }
+        MaterialDesc::Dielectric { ref_idx } => {
+            if *ref_idx <= 0.0 {
+                return Err(error("ref_idx", "must be greater than zero".to_string()));
+            }
+            Arc::new(Dielectric { ref_idx: *ref_idx })
+        }
+        MaterialDesc::Dispersive {
+            glass,
+            cauchy,
+            sellmeier,
+        } => {
+            let invalid = |message: &str| SceneError::Invalid {
+                line: Some(line_of(source, m.span().start)),
+                field: format!("materials.{}", name),
+                message: message.to_string(),
+            };
+            let dispersion = match (glass, cauchy, sellmeier) {
+                (Some(glass), None, None) => match glass.as_str() {
+                    "bk7" => Dispersion::BK7,
+                    "sf11" => Dispersion::SF11,
+                    _ => {
+                        return Err(error(
+                            "glass",
+                            format!("unknown glass `{}`, use bk7 or sf11", glass),
+                        ))
+                    }
+                },
+                (None, Some([a, b]), None) => Dispersion::Cauchy { a: *a, b: *b },
+                (None, None, Some(SellmeierDesc { b, c })) => {
+                    Dispersion::Sellmeier { b: *b, c: *c }
+                }
+                _ => {
+                    return Err(invalid(
+                        "needs exactly one of `glass`, `cauchy` and `sellmeier`",
+                    ))
+                }
+            };
+            let visible = [LAMBDA_MIN, LAMBDA_D, LAMBDA_MAX];
+            if !visible.iter().all(|l| dispersion.index(*l) > 0.0) {
+                return Err(invalid(
+                    "the index of refraction must be greater than zero for visible light",
+                ));
+            }
+            Arc::new(Dispersive::new(dispersion))
+        }
+        MaterialDesc::DiffuseLight { emit } => {
+            Arc::new(DiffuseLight::from_texture(texture(emit, "emit")?))
+        }
+        MaterialDesc::Microfacet {
+            base_color,
+            roughness,
+            metallic,
+        } => {
+            for (field, value) in &[("roughness", roughness), ("metallic", metallic)] {
+                if !(0.0..=1.0).contains(*value) {
+                    return Err(error(field, "must be between 0 and 1".to_string()));
+                }
+            }
+            Arc::new(Microfacet::from_texture(
+                texture(base_color, "base_color")?,
+                *roughness,
+                *metallic,
+            ))
+        }
+        MaterialDesc::Isotropic { albedo } => {
+            Arc::new(Isotropic::from_texture(texture(albedo, "albedo")?))
+        }
+    };
+
+    Ok(material)
+}
+
+#[test]
+fn test_scene_from_toml() {
+    use crate::vec3::Color;
+
+    let scene = Scene::from_toml(
+        r#"
+[render]
+image_width = 200
+aspect_ratio = 2.0
+samples_per_pixel = 10
+max_depth = 5
+background = [0.0, 0.0, 0.0]
+
+[camera]
+lookfrom = [0.0, 0.0, 1.0]
+lookat = [0.0, 0.0, 0.0]
+vfov = 90.0
+
+[materials.red]
+type = "lambertian"
+albedo = [0.8, 0.1, 0.1]
+
+[[spheres]]
+center = [0.0, 0.0, -1.0]
+radius = 0.5
+material = "red"
+
+[[spheres]]
+center = [0.0, -100.5, -1.0]
+radius = 100.0
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(scene.world.objects.len(), 2);
+    assert_eq!(
+        scene.settings,
+        RenderSettings {
+            image_width: 200,
+            image_height: 100,
+            samples_per_pixel: 10,
+            max_depth: 5,
+            background: Background::Solid(Color::zero()),
+            spectral: false,
+            tone_map: ToneMap::default(),
+        }
+    );
+}
+
+#[test]
+fn test_scene_errors() {
+    let header = r#"[render]
+image_width = 200
+samples_per_pixel = 10
+max_depth = 5
+
+[camera]
+lookfrom = [0.0, 0.0, 1.0]
+lookat = [0.0, 0.0, 0.0]
+vfov = 90.0
+"#;
+
+    let unknown_material = format!(
+        "{}\n[[spheres]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"nope\"\n",
+        header
+    );
+    let err = Scene::from_toml(&unknown_material).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 14: spheres[0].material: unknown material `nope`"
+    );
+
+    let bad_fuzz = format!(
+        "{}\n[materials.shiny]\ntype = \"metal\"\nalbedo = [1.0, 1.0, 1.0]\nfuzz = 2.0\n",
+        header
+    );
+    let err = Scene::from_toml(&bad_fuzz).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 14: materials.shiny.fuzz: must be between 0 and 1"
+    );
+
+    let no_glass = format!(
+        "{}\n[materials.prism]\ntype = \"dispersive\"\n\n[materials.glass]\ntype = \"dispersive\"\nglass = \"bk7\"\n",
+        header
+    );
+    let err = Scene::from_toml(&no_glass).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 11: materials.prism: needs exactly one of `glass`, `cauchy` and `sellmeier`"
+    );
+
+    let inline = format!(
+        "{}\n[materials]\nrough = {{ type = \"microfacet\", base_color = [1.0, 1.0, 1.0], roughness = 1.5 }}\n",
+        header
+    );
+    let err = Scene::from_toml(&inline).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 12: materials.rough.roughness: must be between 0 and 1"
+    );
+
+    let bad_tonemap = header.replace("max_depth = 5", "max_depth = 5\ntonemap = \"filmic\"");
+    let err = Scene::from_toml(&bad_tonemap).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 5: render.tonemap: unknown tone mapping `filmic`, use one of clamp, reinhard, aces, agx"
+    );
+
+    let cycle = format!(
+        "{}\n[textures.a]\ntype = \"checker\"\nodd = \"b\"\neven = [1.0, 1.0, 1.0]\n\n[textures.b]\ntype = \"checker\"\nodd = [0.0, 0.0, 0.0]\neven = \"a\"\n",
+        header
+    );
+    let err = Scene::from_toml(&cycle).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 19: textures.b.even: texture `a` contains itself"
+    );
+
+    let zero_scale = format!(
+        "{}\n[textures.floor]\ntype = \"checker\"\nodd = [0.0, 0.0, 0.0]\neven = [1.0, 1.0, 1.0]\nscale = 0.0\n",
+        header
+    );
+    let err = Scene::from_toml(&zero_scale).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 15: textures.floor.scale: must be greater than zero"
+    );
+
+    let flat = format!(
+        "{}\n[[quads]]\nq = [0.0, 0.0, 0.0]\nu = [1.0, 0.0, 0.0]\nv = [2.0, 0.0, 0.0]\n",
+        header
+    );
+    let err = Scene::from_toml(&flat).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 14: quads[0].v: must not be parallel to `u`"
+    );
+
+    let line = format!(
+        "{}
+[[triangles]]
+vertices = [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [2.0, 2.0, 2.0]]
+",
+        header
+    );
+    let err = Scene::from_toml(&line).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 12: triangles[0].vertices: must not be in a line"
+    );
+
+    let zero_normal = format!(
+        "{}
+[[triangles]]
+vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+normals = [[0.0, 0.0, 1.0], [0.0, 0.0, 0.0], [0.0, 0.0, 1.0]]
+",
+        header
+    );
+    let err = Scene::from_toml(&zero_normal).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 13: triangles[0].normals: must not be zero"
+    );
+
+    let fog = format!(
+        "{}\n[[spheres]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\ndensity = 0.5\n",
+        header
+    );
+    let err = Scene::from_toml(&fog).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 14: spheres[0].material: is needed to fill the shape"
+    );
+
+    let shutter = format!("{}shutter_open = 1.0\nshutter_close = 0.5\n", header);
+    let err = Scene::from_toml(&shutter).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 11: camera.shutter_close: must not be before `shutter_open`"
+    );
+
+    let err = Scene::from_toml(&header.replace("max_depth = 5", "max_depth = 0"))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "line 4: render.max_depth: must be greater than zero"
+    );
+
+    let err = Scene::from_toml(&header.replace("vfov = 90.0", "vfov = \"wide\""))
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("line 9"), "{}", err);
+}
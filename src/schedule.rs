@@ -0,0 +1,149 @@
+use rayon::prelude::*;
+
+use crate::framebuffer::{luminance, Framebuffer};
+use crate::sampler::{Sampler, SamplerKind};
+use crate::vec3::Color;
+
+/// Samples every pixel gets before its noise is trusted.
+pub const MIN_ADAPTIVE_SAMPLES: u32 = 16;
+
+/// How many times `samples_per_pixel` a pixel may get with adaptive sampling.
+pub const MAX_ADAPTIVE_FACTOR: u32 = 8;
+
+/// Decides how many samples each pixel gets in each pass. Passes get longer
+/// up to 16 samples, so the first images come quickly.
+///
+/// With `adaptive` set pixels stop once their `Framebuffer::error` is below
+/// it, and the samples saved go to the noisy ones instead: the total stays at
+/// `samples_per_pixel` per pixel on average, but one pixel can get up to
+/// `MAX_ADAPTIVE_FACTOR` times that.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Schedule {
+    pub samples_per_pixel: u32,
+    pub adaptive: Option<f64>,
+}
+
+impl Schedule {
+    /// Samples for every pixel in pass number `pass`. All zero once the
+    /// render is done.
+    pub fn pass(&self, pass: u64, framebuffer: &Framebuffer) -> Vec<u32> {
+        let length = 1 << pass.min(4);
+        let pixels = framebuffer.samples.len() as u64;
+        let taken: u64 = framebuffer.samples.iter().map(|n| *n as u64).sum();
+
+        match self.adaptive {
+            None => framebuffer
+                .samples
+                .iter()
+                .map(|n| length.min(self.samples_per_pixel.saturating_sub(*n)))
+                .collect(),
+            Some(_) if taken >= self.samples_per_pixel as u64 * pixels => vec![0; pixels as usize],
+            Some(threshold) => {
+                let max = self.samples_per_pixel.max(MIN_ADAPTIVE_SAMPLES) * MAX_ADAPTIVE_FACTOR;
+                (0..framebuffer.samples.len())
+                    .map(|i| {
+                        let n = framebuffer.samples[i];
+                        if n < MIN_ADAPTIVE_SAMPLES {
+                            length.min(MIN_ADAPTIVE_SAMPLES - n)
+                        } else if framebuffer.error(i) > threshold {
+                            length.min(max.saturating_sub(n))
+                        } else {
+                            0
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Takes the `samples` from `pass` for every pixel and adds them to
+    /// `framebuffer`, with samplers of `kind` seeded with `seed` carrying on
+    /// from the samples the pixel already has. `sample` gives the color of a
+    /// sample for the pixel at `i`, `j`, counting from the bottom left, and
+    /// the direct light in it.
+    pub fn render_pass<F>(
+        &self,
+        framebuffer: &mut Framebuffer,
+        samples: &[u32],
+        kind: SamplerKind,
+        seed: u64,
+        sample: F,
+    ) where
+        F: Fn(i32, i32, &mut dyn Sampler) -> (Color, Color) + Sync,
+    {
+        let (width, height) = (framebuffer.width, framebuffer.height);
+        let taken = &framebuffer.samples;
+        let results = (0..width * height)
+            .into_par_iter()
+            .map(|index| {
+                // Scanlines are numbered bottom up but images are stored top
+                // down.
+                let i = (index % width) as i32;
+                let j = (height - 1 - index / width) as i32;
+                let mut sampler = kind.create(seed, self.samples_per_pixel);
+
+                let mut sum = Color::zero();
+                let mut direct = Color::zero();
+                let mut luminance_sq = 0.0;
+                for n in taken[index]..taken[index] + samples[index] {
+                    sampler.start_pixel_sample(i as u32, j as u32, n);
+                    let (pixel_color, pixel_direct) = sample(i, j, &mut *sampler);
+                    sum += pixel_color;
+                    direct += pixel_direct;
+                    luminance_sq += luminance(pixel_color).powi(2);
+                }
+                (sum, direct, luminance_sq)
+            })
+            .collect::<Vec<_>>();
+        for (index, (sum, direct, luminance_sq)) in results.into_iter().enumerate() {
+            framebuffer.add(index, sum, luminance_sq, samples[index]);
+            framebuffer.add_direct(index, direct);
+        }
+    }
+}
+
+#[test]
+fn test_schedule() {
+    // Without adaptive sampling every pixel gets the same.
+    let mut framebuffer = Framebuffer::new(2, 1);
+    let fixed = Schedule {
+        samples_per_pixel: 20,
+        adaptive: None,
+    };
+    let passes = (0..)
+        .map(|pass| {
+            let samples = fixed.pass(pass, &framebuffer);
+            for (i, n) in samples.iter().enumerate() {
+                framebuffer.samples[i] += n;
+            }
+            samples[0]
+        })
+        .take_while(|n| *n > 0)
+        .collect::<Vec<_>>();
+    assert_eq!(passes, vec![1, 2, 4, 8, 5]);
+
+    // A flat pixel stops after the minimum, a noisy one carries on.
+    let mut framebuffer = Framebuffer::new(2, 1);
+    let adaptive = Schedule {
+        samples_per_pixel: 32,
+        adaptive: Some(0.01),
+    };
+    let gray = Color::new(0.5, 0.5, 0.5);
+    for pass in 0..100 {
+        let samples = adaptive.pass(pass, &framebuffer);
+        for _ in 0..samples[0] {
+            framebuffer.add(0, gray, luminance(gray).powi(2), 1);
+        }
+        for _ in 0..samples[1] {
+            let c = if framebuffer.samples[1] % 2 == 1 {
+                Color::zero()
+            } else {
+                gray
+            };
+            framebuffer.add(1, c, luminance(c).powi(2), 1);
+        }
+    }
+    assert_eq!(framebuffer.samples[0], MIN_ADAPTIVE_SAMPLES);
+    // Until the budget of both pixels is used up.
+    assert_eq!(framebuffer.samples[1], 2 * 32 - MIN_ADAPTIVE_SAMPLES);
+}
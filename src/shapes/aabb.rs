@@ -0,0 +1,119 @@
+use crate::ray::Ray;
+use crate::vec3::Point3;
+
+/// Axis-aligned bounding box, stored as its minimum and maximum corners.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub minimum: Point3,
+    pub maximum: Point3,
+}
+
+impl Aabb {
+    pub fn new(minimum: Point3, maximum: Point3) -> Aabb {
+        Aabb { minimum, maximum }
+    }
+
+    /// A box that contains nothing. Surrounding it with another box yields
+    /// the other box unchanged.
+    pub fn empty() -> Aabb {
+        Aabb {
+            minimum: Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            maximum: Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
+        let small = Point3::new(
+            box0.minimum.x.min(box1.minimum.x),
+            box0.minimum.y.min(box1.minimum.y),
+            box0.minimum.z.min(box1.minimum.z),
+        );
+        let big = Point3::new(
+            box0.maximum.x.max(box1.maximum.x),
+            box0.maximum.y.max(box1.maximum.y),
+            box0.maximum.z.max(box1.maximum.z),
+        );
+
+        Aabb::new(small, big)
+    }
+
+    /// Grows any side thinner than a small epsilon, so flat objects like
+    /// triangles don't end up with a box the slab test can never hit.
+    pub fn pad(&self) -> Aabb {
+        const DELTA: f64 = 0.0001;
+        let mut padded = *self;
+        for a in 0..3 {
+            if padded.maximum[a] - padded.minimum[a] < DELTA {
+                padded.minimum[a] -= DELTA / 2.0;
+                padded.maximum[a] += DELTA / 2.0;
+            }
+        }
+        padded
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    /// Surface area of the box, used as the cost heuristic when building a BVH.
+    pub fn surface_area(&self) -> f64 {
+        let d = self.maximum - self.minimum;
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Slab test. Only answers whether the ray enters the box somewhere in
+    /// the interval, no hit record is produced.
+    #[inline]
+    pub fn hit(&self, r: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        for a in 0..3 {
+            let inv_d = 1.0 / r.direction[a];
+            let mut t0 = (self.minimum[a] - r.origin[a]) * inv_d;
+            let mut t1 = (self.maximum[a] - r.origin[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[test]
+fn test_aabb_hit() {
+    use crate::vec3::Vec3;
+
+    let b = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+
+    let toward = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+    assert!(b.hit(&toward, 0.0, f64::INFINITY));
+    // The box is behind the end of the interval.
+    assert!(!b.hit(&toward, 0.0, 3.0));
+
+    let away = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(!b.hit(&away, 0.0, f64::INFINITY));
+
+    let beside = Ray::new(Point3::new(2.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+    assert!(!b.hit(&beside, 0.0, f64::INFINITY));
+}
+
+#[test]
+fn test_aabb_surrounding_box() {
+    let a = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+    let b = Aabb::new(Point3::new(-1.0, 0.5, 0.0), Point3::new(0.5, 2.0, 3.0));
+
+    let s = Aabb::surrounding_box(&a, &b);
+    assert_eq!(s.minimum, Point3::new(-1.0, 0.0, 0.0));
+    assert_eq!(s.maximum, Point3::new(1.0, 2.0, 3.0));
+
+    assert_eq!(Aabb::surrounding_box(&Aabb::empty(), &a), a);
+    assert_eq!(a.surface_area(), 6.0);
+    assert_eq!(Aabb::empty().surface_area(), 0.0);
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use super::hittable_list::HittableList;
+use super::quad::Quad;
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
+
+/// An axis-aligned box made of six quads facing outwards. Use `Transformed`
+/// to rotate it.
+pub struct BoxShape {
+    pub minimum: Point3,
+    pub maximum: Point3,
+    sides: HittableList,
+}
+
+impl BoxShape {
+    /// `a` and `b` are opposite corners, in any order.
+    pub fn new(a: Point3, b: Point3, mat_ptr: Option<Arc<dyn Material + Sync + Send>>) -> BoxShape {
+        let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+        let mut sides = HittableList::new();
+        let mut add = |q: Point3, u: Vec3, v: Vec3| {
+            sides.add(Box::new(Quad::new(q, u, v, mat_ptr.clone())));
+        };
+        add(Point3::new(min.x, min.y, max.z), dx, dy); // front
+        add(Point3::new(max.x, min.y, max.z), -dz, dy); // right
+        add(Point3::new(max.x, min.y, min.z), -dx, dy); // back
+        add(Point3::new(min.x, min.y, min.z), dz, dy); // left
+        add(Point3::new(min.x, max.y, max.z), dx, -dz); // top
+        add(Point3::new(min.x, min.y, min.z), dx, dz); // bottom
+
+        BoxShape {
+            minimum: min,
+            maximum: max,
+            sides,
+        }
+    }
+}
+
+impl Hittable for BoxShape {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        self.sides.hit(r, t_min, t_max, rec, sampler)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        *output_box = Aabb::new(self.minimum, self.maximum).pad();
+        true
+    }
+}
+
+#[test]
+fn test_box_shape() {
+    use crate::utils::Rng;
+
+    let b = BoxShape::new(
+        Point3::new(1.0, 1.0, 1.0),
+        Point3::new(-1.0, -1.0, -1.0),
+        None,
+    );
+
+    // Every side faces outwards.
+    let mut rec = HitRecord::new();
+    let mut sampler = Rng::new(0);
+    for a in 0..3 {
+        for &sign in &[-1.0, 1.0] {
+            let mut origin = Point3::zero();
+            origin[a] = 5.0 * sign;
+            let r = Ray::new(origin, -origin, 0.0);
+            assert!(b.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+            assert!(rec.front_face);
+            assert_eq!(rec.normal, origin / 5.0);
+            assert_eq!(rec.t, 0.8);
+        }
+    }
+
+    // From the inside the back faces are hit.
+    let r = Ray::new(Point3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.0);
+    assert!(b.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert!(!rec.front_face);
+}
@@ -0,0 +1,251 @@
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use super::hittable_list::HittableList;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Point3;
+
+/// Number of buckets the centroids are binned into when looking for the
+/// cheapest split. More buckets find slightly better trees but build slower.
+const SAH_BUCKETS: usize = 12;
+
+/// Bounding volume hierarchy. Each node owns its children and a box enclosing
+/// both of them, so whole subtrees are skipped when a ray misses the box.
+pub struct BvhNode {
+    left: Box<dyn Hittable + Send + Sync>,
+    right: Option<Box<dyn Hittable + Send + Sync>>,
+    bbox: Aabb,
+}
+
+struct BuildItem {
+    bbox: Aabb,
+    centroid: Point3,
+    object: Box<dyn Hittable + Send + Sync>,
+}
+
+impl BvhNode {
+    /// Builds a hierarchy over everything in the list using the surface area
+    /// heuristic. Moving objects are bounded over the time interval. Panics if
+    /// an object has no bounding box.
+    pub fn new(list: HittableList, time0: f64, time1: f64) -> BvhNode {
+        let mut items: Vec<BuildItem> = list
+            .objects
+            .into_iter()
+            .map(|object| {
+                let mut bbox = Aabb::empty();
+                if !object.bounding_box(time0, time1, &mut bbox) {
+                    panic!("No bounding box in BvhNode constructor.");
+                }
+                BuildItem {
+                    bbox,
+                    centroid: bbox.centroid(),
+                    object,
+                }
+            })
+            .collect();
+
+        match items.len() {
+            0 => BvhNode {
+                left: Box::new(HittableList::new()),
+                right: None,
+                bbox: Aabb::empty(),
+            },
+            1 => {
+                let item = items.pop().unwrap();
+                BvhNode {
+                    left: item.object,
+                    right: None,
+                    bbox: item.bbox,
+                }
+            }
+            _ => BvhNode::split(items),
+        }
+    }
+
+    fn build(mut items: Vec<BuildItem>) -> Box<dyn Hittable + Send + Sync> {
+        if items.len() == 1 {
+            return items.pop().unwrap().object;
+        }
+
+        Box::new(BvhNode::split(items))
+    }
+
+    fn split(items: Vec<BuildItem>) -> BvhNode {
+        let (left_items, right_items) = partition(items);
+
+        let left_box = bounds(&left_items);
+        let right_box = bounds(&right_items);
+
+        BvhNode {
+            left: BvhNode::build(left_items),
+            right: Some(BvhNode::build(right_items)),
+            bbox: Aabb::surrounding_box(&left_box, &right_box),
+        }
+    }
+}
+
+fn bounds(items: &[BuildItem]) -> Aabb {
+    items
+        .iter()
+        .fold(Aabb::empty(), |acc, item| Aabb::surrounding_box(&acc, &item.bbox))
+}
+
+fn bucket_index(centroid: f64, min: f64, extent: f64) -> usize {
+    let b = ((centroid - min) / extent * SAH_BUCKETS as f64) as usize;
+    b.min(SAH_BUCKETS - 1)
+}
+
+/// Splits the items in two, picking the axis and bucket boundary with the
+/// lowest surface area heuristic cost. Both halves are always non-empty.
+fn partition(items: Vec<BuildItem>) -> (Vec<BuildItem>, Vec<BuildItem>) {
+    let centroid_bounds = items.iter().fold(Aabb::empty(), |acc, item| {
+        Aabb::surrounding_box(&acc, &Aabb::new(item.centroid, item.centroid))
+    });
+
+    // (cost, axis, last bucket on the left side)
+    let mut best: Option<(f64, usize, usize)> = None;
+
+    for axis in 0..3 {
+        let min = centroid_bounds.minimum[axis];
+        let extent = centroid_bounds.maximum[axis] - min;
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut counts = [0usize; SAH_BUCKETS];
+        let mut boxes = [Aabb::empty(); SAH_BUCKETS];
+        for item in &items {
+            let b = bucket_index(item.centroid[axis], min, extent);
+            counts[b] += 1;
+            boxes[b] = Aabb::surrounding_box(&boxes[b], &item.bbox);
+        }
+
+        // Sweep from the right so the cost of every split is known in one pass
+        // over the buckets in each direction.
+        let mut right_areas = [0.0; SAH_BUCKETS];
+        let mut right_counts = [0usize; SAH_BUCKETS];
+        let mut acc_box = Aabb::empty();
+        let mut acc_count = 0;
+        for b in (1..SAH_BUCKETS).rev() {
+            acc_box = Aabb::surrounding_box(&acc_box, &boxes[b]);
+            acc_count += counts[b];
+            right_areas[b] = acc_box.surface_area();
+            right_counts[b] = acc_count;
+        }
+
+        let mut acc_box = Aabb::empty();
+        let mut acc_count = 0;
+        for b in 0..SAH_BUCKETS - 1 {
+            acc_box = Aabb::surrounding_box(&acc_box, &boxes[b]);
+            acc_count += counts[b];
+            if acc_count == 0 || right_counts[b + 1] == 0 {
+                continue;
+            }
+
+            let cost = acc_count as f64 * acc_box.surface_area()
+                + right_counts[b + 1] as f64 * right_areas[b + 1];
+            let cheaper = match best {
+                Some((best_cost, _, _)) => cost < best_cost,
+                None => true,
+            };
+            if cheaper {
+                best = Some((cost, axis, b));
+            }
+        }
+    }
+
+    match best {
+        Some((_, axis, split)) => {
+            let min = centroid_bounds.minimum[axis];
+            let extent = centroid_bounds.maximum[axis] - min;
+            items
+                .into_iter()
+                .partition(|item| bucket_index(item.centroid[axis], min, extent) <= split)
+        }
+        None => {
+            // Every centroid is in the same spot, there is nothing to gain
+            // from being clever so just split the list in half.
+            let mut left = items;
+            let right = left.split_off(left.len() / 2);
+            (left, right)
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return false;
+        }
+
+        let hit_left = self.left.hit(r, t_min, t_max, rec, sampler);
+        let hit_right = match &self.right {
+            Some(right) => {
+                let t_max = if hit_left { rec.t } else { t_max };
+                right.hit(r, t_min, t_max, rec, sampler)
+            }
+            None => false,
+        };
+
+        hit_left || hit_right
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        *output_box = self.bbox;
+        true
+    }
+}
+
+#[test]
+fn test_bvh_matches_list() {
+    use super::sphere::Sphere;
+    use crate::utils::Rng;
+    use crate::vec3::Vec3;
+
+    let make_list = || {
+        let mut list = HittableList::new();
+        for a in -5..5 {
+            for b in -5..5 {
+                let center = Point3::new(a as f64, 0.1 * (a * b) as f64, b as f64);
+                list.add(Box::new(Sphere::new(center, 0.3, None)));
+            }
+        }
+        list
+    };
+
+    let list = make_list();
+    let bvh = BvhNode::new(make_list(), 0.0, 0.0);
+
+    let mut list_box = Aabb::empty();
+    let mut bvh_box = Aabb::empty();
+    assert!(list.bounding_box(0.0, 0.0, &mut list_box));
+    assert!(bvh.bounding_box(0.0, 0.0, &mut bvh_box));
+    assert_eq!(list_box, bvh_box);
+
+    let origin = Point3::new(0.5, 20.0, 0.5);
+    let mut sampler = Rng::new(0);
+    for i in 0..40 {
+        for j in 0..40 {
+            let target = Point3::new(-6.0 + 0.3 * i as f64, 0.0, -6.0 + 0.3 * j as f64);
+            let r = Ray::new(origin, target - origin + Vec3::new(0.0, -0.01, 0.0), 0.0);
+
+            let mut list_rec = HitRecord::new();
+            let mut bvh_rec = HitRecord::new();
+            let list_hit = list.hit(&r, 0.001, f64::INFINITY, &mut list_rec, &mut sampler);
+            let bvh_hit = bvh.hit(&r, 0.001, f64::INFINITY, &mut bvh_rec, &mut sampler);
+
+            assert_eq!(list_hit, bvh_hit);
+            if list_hit {
+                assert_eq!(list_rec.t, bvh_rec.t);
+                assert_eq!(list_rec.p, bvh_rec.p);
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+/// A volume of constant density filling a closed boundary, like smoke or
+/// fog. Rays passing through may scatter anywhere inside, the further they
+/// travel the more likely it gets. The boundary has to be convex, a ray is
+/// assumed to leave it the next time it hits it.
+pub struct ConstantMedium {
+    pub boundary: Box<dyn Hittable + Send + Sync>,
+    pub neg_inv_density: f64,
+    /// Usually `Isotropic`.
+    pub phase_function: Arc<dyn Material + Sync + Send>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Box<dyn Hittable + Send + Sync>,
+        density: f64,
+        phase_function: Arc<dyn Material + Sync + Send>,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        // Where the ray enters and leaves the boundary, even if it starts
+        // inside.
+        let mut rec1 = HitRecord::new();
+        let mut rec2 = HitRecord::new();
+        if !self
+            .boundary
+            .hit(r, f64::NEG_INFINITY, f64::INFINITY, &mut rec1, sampler)
+        {
+            return false;
+        }
+        if !self
+            .boundary
+            .hit(r, rec1.t + 0.0001, f64::INFINITY, &mut rec2, sampler)
+        {
+            return false;
+        }
+
+        let t_enter = rec1.t.max(t_min).max(0.0);
+        let t_exit = rec2.t.min(t_max);
+        if t_enter >= t_exit {
+            return false;
+        }
+
+        let ray_length = r.direction.length();
+        let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * sampler.get_1d().ln();
+        if hit_distance > distance_inside_boundary {
+            return false;
+        }
+
+        rec.t = t_enter + hit_distance / ray_length;
+        rec.p = r.at(rec.t);
+        // Neither is meaningful inside a volume.
+        rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        rec.front_face = true;
+        rec.mat_ptr = Some(self.phase_function.clone());
+
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        self.boundary.bounding_box(time0, time1, output_box)
+    }
+}
+
+#[test]
+fn test_constant_medium() {
+    use super::sphere::Sphere;
+    use crate::materials::isotropic::Isotropic;
+    use crate::utils::Rng;
+    use crate::vec3::{Color, Point3};
+
+    let fog = |density| {
+        ConstantMedium::new(
+            Box::new(Sphere::new(Point3::zero(), 1.0, None)),
+            density,
+            Arc::new(Isotropic::new(Color::ones())),
+        )
+    };
+
+    // Very dense fog scatters right at the boundary, very thin fog almost
+    // never.
+    let mut rec = HitRecord::new();
+    let mut sampler = Rng::new(0);
+    let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+    assert!(fog(1e9).hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert!((rec.t - 4.0).abs() < 1e-6);
+    assert!(rec.mat_ptr.is_some());
+    assert!(!fog(1e-12).hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+
+    // Starting inside, the ray scatters before it leaves.
+    let r = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), 0.0);
+    assert!(fog(1e9).hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert!(rec.t < 0.01);
+
+    // Pointing away from the volume.
+    let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(!fog(1e9).hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+
+    // The same ray scatters at a new distance every time, on average one
+    // over the density in.
+    let r = Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+    let medium = fog(4.0);
+    let mut total = 0.0;
+    let mut count = 0;
+    for _ in 0..4000 {
+        if medium.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler) {
+            total += rec.t;
+            count += 1;
+        }
+    }
+    // Crossing 2 units of fog, only 1 in e^8 rays get through.
+    assert!(count > 3900, "{}", count);
+    let mean = total / count as f64;
+    assert!((mean - 0.25).abs() < 0.02, "{}", mean);
+}
@@ -1,7 +1,9 @@
 use std::sync::Arc;
 
+use super::aabb::Aabb;
 use crate::materials::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::{Point3, Vec3};
 
 #[derive(Clone)]
@@ -10,7 +12,18 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
     pub t: f64,
+    /// Surface coordinates of the hit point.
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
+    /// Which object was hit, when it's wrapped in `Tagged`. 0 otherwise.
+    pub object_id: u32,
+}
+
+impl Default for HitRecord {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HitRecord {
@@ -19,21 +32,76 @@ impl HitRecord {
             p: Point3::zero(),
             normal: Point3::zero(),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             mat_ptr: None,
             front_face: false,
+            object_id: 0,
         }
     }
     #[inline]
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
-        self.front_face = r.direction.dot(&outward_normal) < 0.0;
+        self.front_face = r.direction.dot(outward_normal) < 0.0;
         self.normal = if self.front_face {
-            outward_normal.clone()
+            *outward_normal
         } else {
-            -outward_normal.clone()
+            -*outward_normal
         };
     }
 }
 
 pub trait Hittable {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+    /// Finds the closest hit between `t_min` and `t_max`. `sampler` is for
+    /// objects that are hit at random, like volumes.
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool;
+
+    /// Writes a box enclosing the object over the whole time interval into
+    /// `output_box`. Returns false if the object can't be bounded (e.g. an
+    /// empty list).
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool;
+
+    /// Density of sampling `direction` from `origin` with `random`, per unit
+    /// solid angle. Only needed for objects used as lights.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// A random direction from `origin` towards the object.
+    fn random(&self, _origin: &Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+}
+
+/// Lets a shared object be put in the scene as is, e.g. the same model
+/// placed once without a transform and several times with one.
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        (**self).hit(r, t_min, t_max, rec, sampler)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        (**self).bounding_box(time0, time1, output_box)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        (**self).random(origin, sampler)
+    }
 }
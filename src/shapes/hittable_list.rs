@@ -1,11 +1,19 @@
+use super::aabb::Aabb;
 use super::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::{Point3, Vec3};
 
 pub struct HittableList {
     pub objects: Vec<Box<dyn Hittable + Send + Sync>>,
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HittableList {
     pub fn new() -> HittableList {
         HittableList {
@@ -23,19 +31,68 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
         let mut temp_rec = HitRecord::new();
         let mut hit_anything = false;
         let mut closest_so_far = t_max;
 
         for object in &self.objects {
-            if object.hit(r, t_min, closest_so_far, &mut temp_rec) {
+            if object.hit(r, t_min, closest_so_far, &mut temp_rec, sampler) {
                 hit_anything = true;
-                closest_so_far = temp_rec.t.clone();
+                closest_so_far = temp_rec.t;
                 *rec = temp_rec.clone();
             }
         }
 
         hit_anything
     }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        if self.objects.is_empty() {
+            return false;
+        }
+
+        let mut temp_box = Aabb::empty();
+        let mut result = Aabb::empty();
+
+        for object in &self.objects {
+            if !object.bounding_box(time0, time1, &mut temp_box) {
+                return false;
+            }
+            result = Aabb::surrounding_box(&result, &temp_box);
+        }
+
+        *output_box = result;
+        true
+    }
+
+    /// Each object is picked with the same probability.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+
+        let i =
+            ((sampler.get_1d() * self.objects.len() as f64) as usize).min(self.objects.len() - 1);
+        self.objects[i].random(origin, sampler)
+    }
 }
@@ -1,3 +1,14 @@
+pub mod aabb;
+pub mod box_shape;
+pub mod bvh;
+pub mod constant_medium;
 pub mod hittable;
 pub mod hittable_list;
+pub mod moving;
+pub mod moving_sphere;
+pub mod quad;
 pub mod sphere;
+pub mod tagged;
+pub mod transformed;
+pub mod triangle;
+pub mod triangle_mesh;
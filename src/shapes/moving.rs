@@ -0,0 +1,107 @@
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+/// Moves any object in a straight line over time, offset by `offset0` at
+/// `time0` and by `offset1` at `time1`. Rays are moved the other way instead
+/// of moving the object, so the wrapped object doesn't need to know about
+/// time at all.
+pub struct Moving {
+    pub object: Box<dyn Hittable + Send + Sync>,
+    pub offset0: Vec3,
+    pub offset1: Vec3,
+    pub time0: f64,
+    pub time1: f64,
+}
+
+impl Moving {
+    pub fn new(
+        object: Box<dyn Hittable + Send + Sync>,
+        offset0: Vec3,
+        offset1: Vec3,
+        time0: f64,
+        time1: f64,
+    ) -> Moving {
+        Moving {
+            object,
+            offset0,
+            offset1,
+            time0,
+            time1,
+        }
+    }
+
+    pub fn offset(&self, time: f64) -> Vec3 {
+        if self.time1 == self.time0 {
+            return self.offset0;
+        }
+        self.offset0
+            + ((time - self.time0) / (self.time1 - self.time0)) * (self.offset1 - self.offset0)
+    }
+}
+
+impl Hittable for Moving {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let offset = self.offset(r.time);
+        let moved_r = Ray::new(r.origin - offset, r.direction, r.time);
+        if !self.object.hit(&moved_r, t_min, t_max, rec, sampler) {
+            return false;
+        }
+
+        rec.p += offset;
+
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        let mut object_box = Aabb::empty();
+        if !self.object.bounding_box(time0, time1, &mut object_box) {
+            return false;
+        }
+
+        let shifted =
+            |offset: Vec3| Aabb::new(object_box.minimum + offset, object_box.maximum + offset);
+        *output_box =
+            Aabb::surrounding_box(&shifted(self.offset(time0)), &shifted(self.offset(time1)));
+        true
+    }
+}
+
+#[test]
+fn test_moving() {
+    use super::sphere::Sphere;
+    use crate::utils::Rng;
+    use crate::vec3::Point3;
+
+    let moving = Moving::new(
+        Box::new(Sphere::new(Point3::new(0.0, 0.0, -5.0), 0.5, None)),
+        Vec3::zero(),
+        Vec3::new(0.0, 2.0, 0.0),
+        0.0,
+        1.0,
+    );
+
+    let mut rec = HitRecord::new();
+
+    let mut sampler = Rng::new(0);
+    let r = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.5);
+    assert!(moving.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert_eq!(rec.p, Point3::new(0.0, 1.0, -4.5));
+
+    let r = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(!moving.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+
+    let mut bbox = Aabb::empty();
+    assert!(moving.bounding_box(0.0, 1.0, &mut bbox));
+    assert_eq!(bbox.minimum, Point3::new(-0.5, -0.5, -5.5));
+    assert_eq!(bbox.maximum, Point3::new(0.5, 2.5, -4.5));
+}
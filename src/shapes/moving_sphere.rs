@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use super::sphere::hit_sphere;
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
+
+/// A sphere moving in a straight line, at `center0` at `time0` and at
+/// `center1` at `time1`.
+pub struct MovingSphere {
+    pub center0: Point3,
+    pub center1: Point3,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Point3,
+        center1: Point3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+    ) -> MovingSphere {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            mat_ptr,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Point3 {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        self.center0
+            + ((time - self.time0) / (self.time1 - self.time0)) * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        if !hit_sphere(self.center(r.time), self.radius, r, t_min, t_max, rec) {
+            return false;
+        }
+
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        let r = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        let box0 = Aabb::new(self.center(time0) - r, self.center(time0) + r);
+        let box1 = Aabb::new(self.center(time1) - r, self.center(time1) + r);
+        *output_box = Aabb::surrounding_box(&box0, &box1);
+        true
+    }
+}
+
+#[test]
+fn test_moving_sphere() {
+    use crate::utils::Rng;
+
+    let sphere = MovingSphere::new(
+        Point3::new(0.0, 0.0, -5.0),
+        Point3::new(2.0, 0.0, -5.0),
+        0.0,
+        1.0,
+        0.5,
+        None,
+    );
+    assert_eq!(sphere.center(0.5), Point3::new(1.0, 0.0, -5.0));
+
+    let mut rec = HitRecord::new();
+
+    let mut sampler = Rng::new(0);
+    let r = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(sphere.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert_eq!(rec.t, 4.5);
+
+    // By the end of the shutter interval the sphere has moved out of the way.
+    let r = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0), 1.0);
+    assert!(!sphere.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+
+    let mut bbox = Aabb::empty();
+    assert!(sphere.bounding_box(0.0, 1.0, &mut bbox));
+    assert_eq!(bbox.minimum, Point3::new(-0.5, -0.5, -5.5));
+    assert_eq!(bbox.maximum, Point3::new(2.5, 0.5, -4.5));
+}
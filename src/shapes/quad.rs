@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
+
+/// A parallelogram with a corner at `q` and sides `u` and `v`. The front is
+/// the side `u x v` points to.
+pub struct Quad {
+    pub q: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+    normal: Vec3,
+    d: f64,
+    /// Turns a point in the plane into its coordinates along `u` and `v`.
+    w: Vec3,
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(
+        q: Point3,
+        u: Vec3,
+        v: Vec3,
+        mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+    ) -> Quad {
+        let n = u.cross(&v);
+        let normal = n.unit();
+
+        Quad {
+            q,
+            u,
+            v,
+            mat_ptr,
+            normal,
+            d: normal.dot(&q),
+            w: n / n.dot(&n),
+            area: n.length(),
+        }
+    }
+
+    /// Rectangle in the plane z = k, facing +z.
+    pub fn xy_rect(
+        x0: f64,
+        x1: f64,
+        y0: f64,
+        y1: f64,
+        k: f64,
+        mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+    ) -> Quad {
+        Quad::new(
+            Point3::new(x0, y0, k),
+            Vec3::new(x1 - x0, 0.0, 0.0),
+            Vec3::new(0.0, y1 - y0, 0.0),
+            mat_ptr,
+        )
+    }
+
+    /// Rectangle in the plane y = k, facing +y.
+    pub fn xz_rect(
+        x0: f64,
+        x1: f64,
+        z0: f64,
+        z1: f64,
+        k: f64,
+        mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+    ) -> Quad {
+        Quad::new(
+            Point3::new(x0, k, z0),
+            Vec3::new(0.0, 0.0, z1 - z0),
+            Vec3::new(x1 - x0, 0.0, 0.0),
+            mat_ptr,
+        )
+    }
+
+    /// Rectangle in the plane x = k, facing +x.
+    pub fn yz_rect(
+        y0: f64,
+        y1: f64,
+        z0: f64,
+        z1: f64,
+        k: f64,
+        mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+    ) -> Quad {
+        Quad::new(
+            Point3::new(k, y0, z0),
+            Vec3::new(0.0, y1 - y0, 0.0),
+            Vec3::new(0.0, 0.0, z1 - z0),
+            mat_ptr,
+        )
+    }
+}
+
+impl Quad {
+    /// Where the ray crosses the quad, as `t` and the position on it along
+    /// `u` and `v`, both from 0 to 1.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let denom = self.normal.dot(&r.direction);
+
+        // The ray is parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(&r.origin)) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let planar = r.at(t) - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some((t, alpha, beta))
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        let (t, alpha, beta) = match self.intersect(r, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        rec.t = t;
+        rec.p = r.at(t);
+        rec.u = alpha;
+        rec.v = beta;
+        rec.set_face_normal(r, &self.normal);
+        rec.mat_ptr = self.mat_ptr.clone();
+
+        true
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let mut bbox = Aabb::empty();
+        for corner in &[
+            self.q,
+            self.q + self.u,
+            self.q + self.v,
+            self.q + self.u + self.v,
+        ] {
+            bbox = Aabb::surrounding_box(&bbox, &Aabb::new(*corner, *corner));
+        }
+        *output_box = bbox.pad();
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let r = Ray::new(*origin, *direction, 0.0);
+        let t = match self.intersect(&r, 0.001, f64::INFINITY) {
+            Some((t, _, _)) => t,
+            None => return 0.0,
+        };
+
+        // Uniform over the area, converted to solid angle.
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = (direction.dot(&self.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let p = self.q + u * self.u + v * self.v;
+        p - *origin
+    }
+}
+
+#[test]
+fn test_quad_hit() {
+    use crate::utils::Rng;
+
+    let quad = Quad::xy_rect(0.0, 2.0, 0.0, 4.0, -1.0, None);
+
+    let mut rec = HitRecord::new();
+
+    let mut sampler = Rng::new(0);
+    let r = Ray::new(Point3::new(0.5, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(quad.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert_eq!(rec.t, 1.0);
+    assert_eq!((rec.u, rec.v), (0.25, 0.25));
+    assert!(rec.front_face);
+    assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+
+    let r = Ray::new(Point3::new(0.5, 1.0, -2.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+    assert!(quad.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert!(!rec.front_face);
+    assert_eq!(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+
+    let r = Ray::new(Point3::new(2.5, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(!quad.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+
+    let mut bbox = Aabb::empty();
+    assert!(quad.bounding_box(0.0, 0.0, &mut bbox));
+    assert!(bbox.minimum.z < -1.0 && bbox.maximum.z > -1.0);
+    assert_eq!((bbox.minimum.x, bbox.maximum.y), (0.0, 4.0));
+
+    // The axis-aligned rectangles face the positive axis.
+    let n = |q: Quad| q.normal;
+    assert_eq!(
+        n(Quad::xz_rect(0.0, 1.0, 0.0, 1.0, 0.0, None)),
+        Vec3::new(0.0, 1.0, 0.0)
+    );
+    assert_eq!(
+        n(Quad::yz_rect(0.0, 1.0, 0.0, 1.0, 0.0, None)),
+        Vec3::new(1.0, 0.0, 0.0)
+    );
+}
@@ -1,8 +1,13 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
-use super::hittable::Hittable;
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
 use crate::materials::Material;
-use crate::vec3::Point3;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
 
 pub struct Sphere {
     pub center: Point3,
@@ -24,51 +29,131 @@ impl Sphere {
     }
 }
 
+/// Surface coordinates of a point on the unit sphere. `u` goes around the
+/// y axis starting at x=-1, `v` goes from the bottom (y=-1) to the top.
+pub fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+    let theta = (-p.y).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+
+    (phi / (2.0 * PI), theta / PI)
+}
+
+/// Intersects the ray with a sphere and fills in everything but the material.
+/// Shared by the static and moving spheres.
+pub(crate) fn hit_sphere(
+    center: Point3,
+    radius: f64,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+    rec: &mut HitRecord,
+) -> bool {
+    let oc = r.origin - center;
+
+    let a = r.direction.length_squared();
+    let half_b = oc.dot(&r.direction);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+
+    if discriminant > 0.0 {
+        let root = discriminant.sqrt();
+
+        for temp in &[(-half_b - root) / a, (-half_b + root) / a] {
+            if *temp < t_max && *temp > t_min {
+                rec.t = *temp;
+                rec.p = r.at(rec.t);
+                let outward_normal = (rec.p - center) / radius;
+                rec.set_face_normal(r, &outward_normal);
+                let (u, v) = get_sphere_uv(&outward_normal);
+                rec.u = u;
+                rec.v = v;
+
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
 impl Hittable for Sphere {
     fn hit(
         &self,
-        r: &crate::ray::Ray,
+        r: &Ray,
         t_min: f64,
         t_max: f64,
-        rec: &mut super::hittable::HitRecord,
+        rec: &mut HitRecord,
+        _sampler: &mut dyn Sampler,
     ) -> bool {
-        let oc = r.origin - self.center;
-
-        let a = r.direction.length_squared();
-        let half_b = oc.dot(&r.direction);
-        let c = oc.length_squared() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
+        if !hit_sphere(self.center, self.radius, r, t_min, t_max, rec) {
+            return false;
+        }
 
-        if discriminant > 0.0 {
-            let root = discriminant.sqrt();
+        // TODO: I don't like this clone here but maybe it's OK
+        rec.mat_ptr = self.mat_ptr.clone();
 
-            let temp = (-half_b - root) / a;
-            if temp < t_max && temp > t_min {
-                rec.t = temp;
-                rec.p = r.at(rec.t);
-                let outward_normal = (rec.p - self.center) / self.radius;
-                rec.set_face_normal(&r, &outward_normal);
+        true
+    }
 
-                // TODO: I don't like this clone here but maybe it's OK
-                rec.mat_ptr = self.mat_ptr.clone();
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let r = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        *output_box = Aabb::new(self.center - r, self.center + r);
+        true
+    }
 
-                return true;
-            }
+    /// Uniform over the cone of directions the sphere covers.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let mut rec = HitRecord::new();
+        if !hit_sphere(
+            self.center,
+            self.radius,
+            &Ray::new(*origin, *direction, 0.0),
+            0.001,
+            f64::INFINITY,
+            &mut rec,
+        ) {
+            return 0.0;
+        }
 
-            let temp = (-half_b + root) / a;
-            if temp < t_max && temp > t_min {
-                rec.t = temp;
-                rec.p = r.at(rec.t);
-                let outward_normal = (rec.p - self.center) / self.radius;
-                rec.set_face_normal(&r, &outward_normal);
+        let distance_squared = (self.center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            // From inside every direction hits it.
+            return 1.0 / (4.0 * PI);
+        }
 
-                // TODO: I don't like this clone here but maybe it's OK
-                rec.mat_ptr = self.mat_ptr.clone();
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
 
-                return true;
-            }
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return Vec3::random_unit_vector(sampler);
         }
 
-        false
+        let (r1, r2) = sampler.get_2d();
+        let z = 1.0 + r2 * ((1.0 - radius_squared / distance_squared).sqrt() - 1.0);
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+
+        Onb::build_from_w(&direction).local(&Vec3::new(x, y, z))
     }
 }
+
+#[test]
+fn test_get_sphere_uv() {
+    let close = |(u, v): (f64, f64), (eu, ev): (f64, f64)| {
+        assert!((u - eu).abs() < 1e-12 && (v - ev).abs() < 1e-12);
+    };
+    close(get_sphere_uv(&Point3::new(1.0, 0.0, 0.0)), (0.5, 0.5));
+    close(get_sphere_uv(&Point3::new(-1.0, 0.0, 0.0)), (0.0, 0.5));
+    close(get_sphere_uv(&Point3::new(0.0, 1.0, 0.0)), (0.5, 1.0));
+    close(get_sphere_uv(&Point3::new(0.0, -1.0, 0.0)), (0.5, 0.0));
+    close(get_sphere_uv(&Point3::new(0.0, 0.0, 1.0)), (0.25, 0.5));
+    close(get_sphere_uv(&Point3::new(0.0, 0.0, -1.0)), (0.75, 0.5));
+}
@@ -0,0 +1,59 @@
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use super::hittable_list::HittableList;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
+
+/// Marks hits on the wrapped object with `id`, so the object ID AOV can
+/// tell objects apart however they are made up inside.
+pub struct Tagged {
+    pub object: Box<dyn Hittable + Send + Sync>,
+    pub id: u32,
+}
+
+impl Tagged {
+    pub fn new(object: Box<dyn Hittable + Send + Sync>, id: u32) -> Tagged {
+        Tagged { object, id }
+    }
+
+    /// Tags every object in `list` with its position in it, starting at 1.
+    pub fn tag_all(list: HittableList) -> HittableList {
+        let mut tagged = HittableList::new();
+        for (i, object) in list.objects.into_iter().enumerate() {
+            tagged.add(Box::new(Tagged::new(object, i as u32 + 1)));
+        }
+        tagged
+    }
+}
+
+impl Hittable for Tagged {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        if !self.object.hit(r, t_min, t_max, rec, sampler) {
+            return false;
+        }
+
+        rec.object_id = self.id;
+
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        self.object.bounding_box(time0, time1, output_box)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.object.random(origin, sampler)
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::transform::Transform;
+use crate::vec3::Point3;
+
+/// Places an object in the scene with an affine transform. The object is
+/// shared, so the same mesh can be instanced any number of times without
+/// copying it.
+pub struct Transformed {
+    pub object: Arc<dyn Hittable + Send + Sync>,
+    pub transform: Transform,
+}
+
+impl Transformed {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, transform: Transform) -> Transformed {
+        Transformed { object, transform }
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        // The direction isn't normalized so distances along the ray are the
+        // same in both spaces.
+        let object_r = Ray::new(
+            self.transform.inverse.transform_point(&r.origin),
+            self.transform.inverse.transform_vector(&r.direction),
+            r.time,
+        );
+        if !self.object.hit(&object_r, t_min, t_max, rec, sampler) {
+            return false;
+        }
+
+        // The side that was hit doesn't change, so `front_face` still holds.
+        rec.p = self.transform.point(&rec.p);
+        rec.normal = self.transform.normal(&rec.normal).unit();
+
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
+        let mut object_box = Aabb::empty();
+        if !self.object.bounding_box(time0, time1, &mut object_box) {
+            return false;
+        }
+
+        // Box around the transformed corners of the object's box.
+        let ends = [object_box.minimum, object_box.maximum];
+        let mut bbox = Aabb::empty();
+        for i in 0..8 {
+            let corner = Point3::new(ends[i & 1].x, ends[(i >> 1) & 1].y, ends[(i >> 2) & 1].z);
+            let p = self.transform.point(&corner);
+            bbox = Aabb::surrounding_box(&bbox, &Aabb::new(p, p));
+        }
+        *output_box = bbox;
+        true
+    }
+}
+
+#[test]
+fn test_transformed() {
+    use super::sphere::Sphere;
+    use crate::utils::Rng;
+    use crate::vec3::Vec3;
+
+    // A unit sphere stretched into an ellipsoid, shared by two instances.
+    let sphere: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(Point3::zero(), 1.0, None));
+    let stretch = Transform::scale(Vec3::new(2.0, 1.0, 1.0));
+    let near = Transformed::new(sphere.clone(), stretch);
+    let far = Transformed::new(
+        sphere,
+        stretch.then(&Transform::translate(Vec3::new(0.0, 0.0, -10.0))),
+    );
+
+    let mut rec = HitRecord::new();
+
+    let mut sampler = Rng::new(0);
+    let r = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+    assert!(near.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert!((rec.t - 3.0).abs() < 1e-12);
+    assert!((rec.p - Point3::new(-2.0, 0.0, 0.0)).length() < 1e-12);
+    assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-12);
+
+    // Off axis the normal has to account for the non-uniform scale.
+    let r = Ray::new(Point3::new(1.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+    assert!(near.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    let p = Vec3::new(0.5, (0.75f64).sqrt(), 0.0);
+    let expected = Vec3::new(p.x / 2.0, p.y, 0.0).unit();
+    assert!((rec.normal - expected).length() < 1e-12);
+
+    let r = Ray::new(Point3::new(0.0, 5.0, -10.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+    assert!(far.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert!(!near.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+
+    let mut bbox = Aabb::empty();
+    assert!(far.bounding_box(0.0, 0.0, &mut bbox));
+    assert_eq!(bbox.minimum, Point3::new(-2.0, -1.0, -11.0));
+    assert_eq!(bbox.maximum, Point3::new(2.0, 1.0, -9.0));
+}
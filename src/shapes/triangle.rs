@@ -0,0 +1,248 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
+
+/// Texture coordinates of a vertex.
+pub type Uv = (f64, f64);
+
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    /// Per-vertex shading normals. The geometric normal is used without them.
+    pub normals: Option<[Vec3; 3]>,
+    /// Per-vertex texture coordinates. The barycentric coordinates of the hit
+    /// are used without them.
+    pub uvs: Option<[Uv; 3]>,
+    pub mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+}
+
+impl Triangle {
+    pub fn new(
+        v0: Point3,
+        v1: Point3,
+        v2: Point3,
+        mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+    ) -> Triangle {
+        Triangle {
+            vertices: [v0, v1, v2],
+            normals: None,
+            uvs: None,
+            mat_ptr,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Triangle {
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [Uv; 3]) -> Triangle {
+        self.uvs = Some(uvs);
+        self
+    }
+}
+
+/// Möller–Trumbore ray/triangle intersection. Returns the distance along the
+/// ray and the barycentric coordinates of the hit for `v1` and `v2`.
+#[inline]
+pub(crate) fn intersect(
+    vertices: &[Point3; 3],
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+
+    let pvec = r.direction.cross(&edge2);
+    let det = edge1.dot(&pvec);
+
+    // The ray is parallel to the triangle.
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin - vertices[0];
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(&edge1);
+    let b2 = r.direction.dot(&qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&qvec) * inv_det;
+    if t < t_max && t > t_min {
+        Some((t, b1, b2))
+    } else {
+        None
+    }
+}
+
+/// Fills in everything but the material for a hit found by `intersect`.
+#[inline]
+pub(crate) fn set_hit_record(
+    vertices: &[Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[Uv; 3]>,
+    r: &Ray,
+    (t, b1, b2): (f64, f64, f64),
+    rec: &mut HitRecord,
+) {
+    let b0 = 1.0 - b1 - b2;
+
+    rec.t = t;
+    rec.p = r.at(t);
+
+    let outward_normal = (vertices[1] - vertices[0])
+        .cross(&(vertices[2] - vertices[0]))
+        .unit();
+    rec.set_face_normal(r, &outward_normal);
+
+    // Which side was hit is decided by the actual surface, the interpolated
+    // normal only changes how it is shaded.
+    if let Some(n) = normals {
+        let shading_normal = (b0 * n[0] + b1 * n[1] + b2 * n[2]).unit();
+        rec.normal = if rec.front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+    }
+
+    let (u, v) = match uvs {
+        Some(uv) => (
+            b0 * uv[0].0 + b1 * uv[1].0 + b2 * uv[2].0,
+            b0 * uv[0].1 + b1 * uv[1].1 + b2 * uv[2].1,
+        ),
+        None => (b1, b2),
+    };
+    rec.u = u;
+    rec.v = v;
+}
+
+pub(crate) fn bounding_box(vertices: &[Point3; 3]) -> Aabb {
+    let mut bbox = Aabb::new(vertices[0], vertices[0]);
+    for v in &vertices[1..] {
+        bbox = Aabb::surrounding_box(&bbox, &Aabb::new(*v, *v));
+    }
+    bbox.pad()
+}
+
+impl Hittable for Triangle {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        match intersect(&self.vertices, r, t_min, t_max) {
+            Some(hit) => {
+                set_hit_record(&self.vertices, self.normals, self.uvs, r, hit, rec);
+                rec.mat_ptr = self.mat_ptr.clone();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        *output_box = bounding_box(&self.vertices);
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let r = Ray::new(*origin, *direction, 0.0);
+        let t = match intersect(&self.vertices, &r, 0.001, f64::INFINITY) {
+            Some((t, _, _)) => t,
+            None => return 0.0,
+        };
+
+        let n = (self.vertices[1] - self.vertices[0]).cross(&(self.vertices[2] - self.vertices[0]));
+        let area = 0.5 * n.length();
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = (direction.dot(&n) / (direction.length() * n.length())).abs();
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        // Uniform over the area.
+        let (u, v) = sampler.get_2d();
+        let s = u.sqrt();
+        let b1 = s * (1.0 - v);
+        let b2 = s - b1;
+        let [v0, v1, v2] = self.vertices;
+        (1.0 - s) * v0 + b1 * v1 + b2 * v2 - *origin
+    }
+}
+
+#[test]
+fn test_triangle_hit() {
+    use crate::utils::Rng;
+
+    let tri = Triangle::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(1.0, 0.0, 0.0),
+        Point3::new(0.0, 1.0, 0.0),
+        None,
+    );
+
+    let mut rec = HitRecord::new();
+
+    let mut sampler = Rng::new(0);
+    let r = Ray::new(Point3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(tri.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert_eq!(rec.t, 1.0);
+    assert_eq!(rec.p, Point3::new(0.25, 0.5, 0.0));
+    assert_eq!((rec.u, rec.v), (0.25, 0.5));
+    assert!(rec.front_face);
+    assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+
+    // From behind the normal is flipped towards the ray.
+    let r = Ray::new(Point3::new(0.25, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+    assert!(tri.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert!(!rec.front_face);
+    assert_eq!(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+
+    let r = Ray::new(Point3::new(0.75, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(!tri.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+
+    let mut bbox = Aabb::empty();
+    assert!(tri.bounding_box(0.0, 0.0, &mut bbox));
+    assert!(bbox.maximum.z > bbox.minimum.z);
+}
+
+#[test]
+fn test_triangle_interpolates_attributes() {
+    use crate::utils::Rng;
+
+    let up = Vec3::new(0.0, 0.0, 1.0);
+    let tilted = Vec3::new(1.0, 0.0, 1.0).unit();
+    let tri = Triangle::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(1.0, 0.0, 0.0),
+        Point3::new(0.0, 1.0, 0.0),
+        None,
+    )
+    .with_normals([up, tilted, up])
+    .with_uvs([(0.0, 0.0), (2.0, 0.0), (0.0, 4.0)]);
+
+    let mut rec = HitRecord::new();
+
+    let mut sampler = Rng::new(0);
+    let r = Ray::new(Point3::new(0.5, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(tri.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+    assert_eq!((rec.u, rec.v), (1.0, 1.0));
+    assert!(rec.normal.x > 0.0);
+    assert!((rec.normal.length() - 1.0).abs() < 1e-12);
+}
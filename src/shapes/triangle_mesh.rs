@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::bvh::BvhNode;
+use super::hittable::{HitRecord, Hittable};
+use super::hittable_list::HittableList;
+use super::triangle::{self, Uv};
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
+
+/// One face of a mesh as indices into the mesh's vertex buffers. Positions,
+/// normals and texture coordinates are indexed separately (like in OBJ
+/// files) so vertices never have to be duplicated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshFace {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+struct MeshData {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Uv>,
+    faces: Vec<MeshFace>,
+    mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+}
+
+/// A single face of a mesh. It only holds a reference to the shared buffers
+/// and its index, the vertices are looked up on every hit.
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+impl MeshTriangle {
+    #[inline]
+    fn vertices(&self) -> [Point3; 3] {
+        let p = &self.mesh.faces[self.face].positions;
+        [
+            self.mesh.positions[p[0]],
+            self.mesh.positions[p[1]],
+            self.mesh.positions[p[2]],
+        ]
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        let vertices = self.vertices();
+        match triangle::intersect(&vertices, r, t_min, t_max) {
+            Some(hit) => {
+                let face = &self.mesh.faces[self.face];
+                let normals = face.normals.map(|n| n.map(|i| self.mesh.normals[i]));
+                let uvs = face.uvs.map(|uv| uv.map(|i| self.mesh.uvs[i]));
+
+                triangle::set_hit_record(&vertices, normals, uvs, r, hit, rec);
+                rec.mat_ptr = self.mesh.mat_ptr.clone();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        *output_box = triangle::bounding_box(&self.vertices());
+        true
+    }
+}
+
+/// A collection of triangles sharing vertex buffers and a material. The faces
+/// are kept in their own BVH so the whole mesh acts as a single object in the
+/// scene.
+pub struct TriangleMesh {
+    bvh: BvhNode,
+    triangle_count: usize,
+}
+
+impl TriangleMesh {
+    /// Panics if a face refers to a vertex, normal or texture coordinate that
+    /// isn't in the buffers.
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<Uv>,
+        faces: Vec<MeshFace>,
+        mat_ptr: Option<Arc<dyn Material + Sync + Send>>,
+    ) -> TriangleMesh {
+        for (i, face) in faces.iter().enumerate() {
+            let check = |indices: &[usize; 3], len: usize, what: &str| {
+                for &index in indices {
+                    assert!(
+                        index < len,
+                        "face {} refers to {} {} but the mesh only has {}",
+                        i,
+                        what,
+                        index,
+                        len
+                    );
+                }
+            };
+            check(&face.positions, positions.len(), "position");
+            if let Some(n) = &face.normals {
+                check(n, normals.len(), "normal");
+            }
+            if let Some(uv) = &face.uvs {
+                check(uv, uvs.len(), "texture coordinate");
+            }
+        }
+
+        let triangle_count = faces.len();
+        let mesh = Arc::new(MeshData {
+            positions,
+            normals,
+            uvs,
+            faces,
+            mat_ptr,
+        });
+
+        let mut list = HittableList::new();
+        for face in 0..triangle_count {
+            list.add(Box::new(MeshTriangle {
+                mesh: mesh.clone(),
+                face,
+            }));
+        }
+
+        TriangleMesh {
+            bvh: BvhNode::new(list, 0.0, 0.0),
+            triangle_count,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.triangle_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangle_count == 0
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        self.bvh.hit(r, t_min, t_max, rec, sampler)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        !self.is_empty() && self.bvh.bounding_box(0.0, 0.0, output_box)
+    }
+}
+
+#[test]
+fn test_triangle_mesh() {
+    use crate::utils::Rng;
+
+    // A unit square in the xy plane made of two triangles.
+    let positions = vec![
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(1.0, 0.0, 0.0),
+        Point3::new(1.0, 1.0, 0.0),
+        Point3::new(0.0, 1.0, 0.0),
+    ];
+    let uvs = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+    let faces = vec![
+        MeshFace {
+            positions: [0, 1, 2],
+            normals: None,
+            uvs: Some([0, 1, 2]),
+        },
+        MeshFace {
+            positions: [0, 2, 3],
+            normals: None,
+            uvs: Some([0, 2, 3]),
+        },
+    ];
+    let mesh = TriangleMesh::new(positions, Vec::new(), uvs, faces, None);
+    assert_eq!(mesh.len(), 2);
+
+    let mut rec = HitRecord::new();
+
+    let mut sampler = Rng::new(0);
+    for &(x, y) in &[(0.75, 0.25), (0.25, 0.75)] {
+        let r = Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(mesh.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+        assert!((rec.u - x).abs() < 1e-12 && (rec.v - y).abs() < 1e-12);
+    }
+
+    let r = Ray::new(Point3::new(1.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+    assert!(!mesh.hit(&r, 0.001, f64::INFINITY, &mut rec, &mut sampler));
+}
+
+#[test]
+#[should_panic]
+fn test_triangle_mesh_bad_index() {
+    let faces = vec![MeshFace {
+        positions: [0, 1, 5],
+        normals: None,
+        uvs: None,
+    }];
+    TriangleMesh::new(vec![Point3::zero(); 3], Vec::new(), Vec::new(), faces, None);
+}
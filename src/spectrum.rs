@@ -0,0 +1,249 @@
+//! Spectral rendering. Each camera sample traces a few wavelengths at once
+//! (hero wavelength sampling): a random hero wavelength plus others spaced
+//! evenly over the visible range. The result is turned into RGB with the CIE
+//! color matching functions.
+//!
+//! Colors in the scene stay RGB and are upsampled to smooth spectra where
+//! they are used, so only materials that really depend on the wavelength,
+//! like `Dispersive`, need to know about any of this.
+
+use std::ops::{Add, Div, Mul};
+use std::sync::OnceLock;
+
+use crate::vec3::Color;
+
+/// The visible range in nanometres.
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+/// Wavelengths traced together.
+pub const SAMPLES: usize = 4;
+
+/// Where the index of refraction is measured when rendering in RGB, the
+/// yellow helium d line.
+pub const LAMBDA_D: f64 = 587.6;
+
+/// The wavelengths a camera sample traces, in nanometres. The first one is
+/// the hero wavelength.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; SAMPLES],
+}
+
+impl SampledWavelengths {
+    /// Picks the hero wavelength uniformly with `u` in [0, 1), the others
+    /// follow it at equal steps, wrapping around the range.
+    pub fn sample(u: f64) -> SampledWavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.0; SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = (u + i as f64 / SAMPLES as f64).fract();
+            *l = LAMBDA_MIN + offset * range;
+        }
+        SampledWavelengths { lambda }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Upsamples an RGB color to a spectrum and evaluates it at these
+    /// wavelengths.
+    pub fn upsample(&self, rgb: Color) -> SampledSpectrum {
+        let mut values = [0.0; SAMPLES];
+        for (value, lambda) in values.iter_mut().zip(&self.lambda) {
+            *value = rgb_to_spectrum(rgb, *lambda);
+        }
+        SampledSpectrum { values }
+    }
+
+    /// The linear RGB color `s` contributes to a pixel. Averaging these over
+    /// many samples converges to the color of the full spectrum.
+    pub fn to_rgb(&self, s: &SampledSpectrum) -> Color {
+        let pdf = 1.0 / (LAMBDA_MAX - LAMBDA_MIN);
+        let mut xyz = Color::zero();
+        for (value, lambda) in s.values.iter().zip(&self.lambda) {
+            xyz += *value * cie_xyz(*lambda) / pdf;
+        }
+        let normalization = normalization();
+        xyz_to_rgb(xyz / (SAMPLES as f64 * normalization.y_integral)) / normalization.white
+    }
+}
+
+/// Radiance or reflectance at each of the `SampledWavelengths`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampledSpectrum {
+    pub values: [f64; SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn zero() -> SampledSpectrum {
+        SampledSpectrum {
+            values: [0.0; SAMPLES],
+        }
+    }
+
+    /// Keeps only the hero wavelength, for paths that went through a
+    /// dispersive material and so are only right for that one. It is
+    /// weighted up so the average over all hero wavelengths stays the same.
+    pub fn hero_only(&self) -> SampledSpectrum {
+        let mut values = [0.0; SAMPLES];
+        values[0] = self.values[0] * SAMPLES as f64;
+        SampledSpectrum { values }
+    }
+
+    fn map(self, f: impl Fn(f64) -> f64) -> SampledSpectrum {
+        let mut values = self.values;
+        for value in values.iter_mut() {
+            *value = f(*value);
+        }
+        SampledSpectrum { values }
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add(self, other: SampledSpectrum) -> SampledSpectrum {
+        let mut values = self.values;
+        for (value, o) in values.iter_mut().zip(&other.values) {
+            *value += o;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, other: SampledSpectrum) -> SampledSpectrum {
+        let mut values = self.values;
+        for (value, o) in values.iter_mut().zip(&other.values) {
+            *value *= o;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, t: f64) -> SampledSpectrum {
+        self.map(|value| value * t)
+    }
+}
+
+impl Div<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn div(self, t: f64) -> SampledSpectrum {
+        self.map(|value| value / t)
+    }
+}
+
+/// Piecewise Gaussian with different widths on either side of the mean.
+fn lobe(lambda: f64, mean: f64, below: f64, above: f64) -> f64 {
+    let t = (lambda - mean) / if lambda < mean { below } else { above };
+    (-0.5 * t * t).exp()
+}
+
+/// The CIE 1931 2° color matching functions, using the multi-lobe fit from
+/// Wyman, Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ
+/// Color Matching Functions" (2013).
+pub fn cie_xyz(lambda: f64) -> Color {
+    Color::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+/// CIE XYZ to linear sRGB.
+pub fn xyz_to_rgb(xyz: Color) -> Color {
+    Color::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+fn logistic(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Smooth spectrum for an RGB color: blue below about 490nm, red above
+/// about 590nm and green in between, with soft edges. The three parts add up
+/// to one everywhere, so white stays flat and a reflectance between 0 and 1
+/// stays between 0 and 1. Saturated colors come out a little duller than
+/// they are in RGB.
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let blue = logistic((490.0 - lambda) / 10.0);
+    let red = logistic((lambda - 590.0) / 10.0);
+    let green = 1.0 - blue - red;
+    rgb.x * red + rgb.y * green + rgb.z * blue
+}
+
+struct Normalization {
+    y_integral: f64,
+    /// The color of a flat spectrum, which is scaled to white.
+    white: Color,
+}
+
+fn normalization() -> &'static Normalization {
+    static NORMALIZATION: OnceLock<Normalization> = OnceLock::new();
+    NORMALIZATION.get_or_init(|| {
+        let steps = 3400;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let mut xyz = Color::zero();
+        for i in 0..steps {
+            xyz += cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step) * step;
+        }
+        Normalization {
+            y_integral: xyz.y,
+            white: xyz_to_rgb(xyz / xyz.y),
+        }
+    })
+}
+
+#[test]
+fn test_spectrum_to_rgb() {
+    let close = |a: Color, b: Color, tolerance: f64| {
+        assert!((a - b).length() < tolerance, "{:?} != {:?}", a, b)
+    };
+
+    // The wavelengths cover the range evenly.
+    let wavelengths = SampledWavelengths::sample(0.9);
+    assert_eq!(wavelengths.hero(), LAMBDA_MIN + 0.9 * 340.0);
+    assert!((wavelengths.lambda[1] - (LAMBDA_MIN + 0.15 * 340.0)).abs() < 1e-9);
+
+    // Averaged over many hero wavelengths, RGB colors come back about the
+    // same, white exactly.
+    let round_trip = |rgb: Color| {
+        let n = 1000;
+        let total = (0..n).fold(Color::zero(), |total, i| {
+            let wavelengths = SampledWavelengths::sample((i as f64 + 0.5) / n as f64);
+            total + wavelengths.to_rgb(&wavelengths.upsample(rgb))
+        });
+        total / n as f64
+    };
+    close(round_trip(Color::ones()), Color::ones(), 1e-6);
+    close(
+        round_trip(Color::new(3.0, 3.0, 3.0)),
+        Color::new(3.0, 3.0, 3.0),
+        1e-5,
+    );
+    close(
+        round_trip(Color::new(0.2, 0.4, 0.8)),
+        Color::new(0.2, 0.4, 0.8),
+        0.15,
+    );
+    let red = round_trip(Color::new(1.0, 0.0, 0.0));
+    assert!(red.x > 0.6 && red.y < 0.2 && red.z < 0.2, "{:?}", red);
+
+    // Only the hero wavelength survives dispersion, with the weight of all.
+    let s = SampledSpectrum {
+        values: [1.0, 2.0, 3.0, 4.0],
+    };
+    assert_eq!(s.hero_only().values, [4.0, 0.0, 0.0, 0.0]);
+}
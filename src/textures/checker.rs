@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use super::Texture;
+use crate::vec3::{Color, Point3};
+
+/// A 3D checkerboard of cubes `scale` wide alternating between two textures.
+/// Because it's solid it doesn't depend on the surface coordinates.
+pub struct CheckerTexture {
+    pub odd: Arc<dyn Texture + Sync + Send>,
+    pub even: Arc<dyn Texture + Sync + Send>,
+    pub scale: f64,
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let inv_scale = 1.0 / self.scale;
+        let x = (inv_scale * p.x).floor() as i64;
+        let y = (inv_scale * p.y).floor() as i64;
+        let z = (inv_scale * p.z).floor() as i64;
+
+        if (x + y + z).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+#[test]
+fn test_checker_texture() {
+    use super::solid_color::SolidColor;
+
+    let black = Color::zero();
+    let white = Color::ones();
+    let checker = CheckerTexture {
+        odd: Arc::new(SolidColor::new(black)),
+        even: Arc::new(SolidColor::new(white)),
+        scale: 0.5,
+    };
+
+    assert_eq!(checker.value(0.0, 0.0, &Point3::new(0.1, 0.1, 0.1)), white);
+    assert_eq!(checker.value(0.0, 0.0, &Point3::new(0.6, 0.1, 0.1)), black);
+    assert_eq!(checker.value(0.0, 0.0, &Point3::new(-0.1, 0.1, 0.1)), black);
+    assert_eq!(
+        checker.value(0.0, 0.0, &Point3::new(-0.1, -0.1, 0.1)),
+        white
+    );
+}
@@ -0,0 +1,55 @@
+use std::io;
+use std::path::Path;
+
+use super::Texture;
+use crate::image::Image;
+use crate::utils;
+use crate::vec3::{Color, Point3};
+
+/// Looks the color up in an image using the surface coordinates. (0, 0) is
+/// the bottom left corner of the image.
+pub struct ImageTexture {
+    pub image: Image,
+}
+
+impl ImageTexture {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ImageTexture> {
+        Ok(ImageTexture {
+            image: Image::load(path)?,
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        // Without any data, return solid cyan as a debugging aid.
+        if self.image.pixels.is_empty() {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        let u = utils::clamp(u, 0.0, 1.0);
+        // Flip v to image coordinates.
+        let v = 1.0 - utils::clamp(v, 0.0, 1.0);
+
+        let i = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+        let j = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+
+        self.image.get(i, j)
+    }
+}
+
+#[test]
+fn test_image_texture() {
+    let mut image = Image::new(2, 2);
+    image.set(0, 0, Color::new(1.0, 0.0, 0.0));
+    image.set(1, 0, Color::new(0.0, 1.0, 0.0));
+    image.set(0, 1, Color::new(0.0, 0.0, 1.0));
+    image.set(1, 1, Color::ones());
+    let texture = ImageTexture { image };
+
+    let p = Point3::zero();
+    assert_eq!(texture.value(0.0, 1.0, &p), Color::new(1.0, 0.0, 0.0));
+    assert_eq!(texture.value(1.0, 1.0, &p), Color::new(0.0, 1.0, 0.0));
+    assert_eq!(texture.value(0.25, 0.25, &p), Color::new(0.0, 0.0, 1.0));
+    assert_eq!(texture.value(2.0, -1.0, &p), Color::ones());
+}
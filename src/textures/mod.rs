@@ -0,0 +1,13 @@
+use crate::vec3::{Color, Point3};
+
+pub mod checker;
+pub mod image_texture;
+pub mod noise;
+pub mod perlin;
+pub mod solid_color;
+
+/// A color that varies over a surface. `u` and `v` are the surface
+/// coordinates of the hit and `p` is the hit point itself.
+pub trait Texture: Sync + Send {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
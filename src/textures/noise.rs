@@ -0,0 +1,119 @@
+//! Procedural textures built on Perlin noise. They only depend on the hit
+//! point, so they work on any shape without surface coordinates.
+
+use super::perlin::Perlin;
+use super::Texture;
+use crate::utils;
+use crate::vec3::{Color, Point3};
+
+fn lerp(a: Color, b: Color, t: f64) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = utils::clamp((x - edge0) / (edge1 - edge0), 0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Plain noise mapped to a grey between black and white.
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(seed: u64, scale: f64) -> NoiseTexture {
+        NoiseTexture {
+            noise: Perlin::new(seed),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        Color::ones() * 0.5 * (1.0 + self.noise.noise(&(self.scale * *p)))
+    }
+}
+
+/// Veins along the z axis, wobbled by turbulence.
+pub struct MarbleTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub color: Color,
+}
+
+impl MarbleTexture {
+    pub fn new(seed: u64, scale: f64, color: Color) -> MarbleTexture {
+        MarbleTexture {
+            noise: Perlin::new(seed),
+            scale,
+            color,
+        }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let phase = self.scale * p.z + 10.0 * self.noise.turb(p, 7);
+        self.color * 0.5 * (1.0 + phase.sin())
+    }
+}
+
+/// Concentric growth rings around the y axis. The noise bends the rings and
+/// adds some fine grain.
+pub struct WoodTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub light: Color,
+    pub dark: Color,
+}
+
+impl WoodTexture {
+    pub fn new(seed: u64, scale: f64, light: Color, dark: Color) -> WoodTexture {
+        WoodTexture {
+            noise: Perlin::new(seed),
+            scale,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let q = self.scale * *p;
+        let radius = (q.x * q.x + q.z * q.z).sqrt();
+        let rings = radius + 0.5 * self.noise.fbm(&(0.5 * q), 3, false);
+        let grain = 0.1 * self.noise.noise(&Point3::new(20.0 * q.x, q.y, 20.0 * q.z));
+
+        let t = smoothstep(0.0, 1.0, (rings.fract() + grain).abs().min(1.0));
+        lerp(self.light, self.dark, t)
+    }
+}
+
+/// Soft white clouds over a sky color.
+pub struct CloudTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub sky: Color,
+    pub cloud: Color,
+}
+
+impl CloudTexture {
+    pub fn new(seed: u64, scale: f64, sky: Color, cloud: Color) -> CloudTexture {
+        CloudTexture {
+            noise: Perlin::new(seed),
+            scale,
+            sky,
+            cloud,
+        }
+    }
+}
+
+impl Texture for CloudTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let density = 0.5 + 0.5 * self.noise.fbm(&(self.scale * *p), 6, false);
+        lerp(self.sky, self.cloud, smoothstep(0.45, 0.75, density))
+    }
+}
@@ -0,0 +1,152 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::vec3::{Point3, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise as described in "Ray Tracing: The Next Week". The random
+/// tables come from a seeded generator so the same seed always gives the
+/// same noise.
+#[derive(Clone)]
+pub struct Perlin {
+    ranvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let ranvec = (0..POINT_COUNT)
+            .map(|_| {
+                Vec3::new(
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                )
+                .unit()
+            })
+            .collect();
+
+        let perm_x = perlin_generate_perm(&mut rng);
+        let perm_y = perlin_generate_perm(&mut rng);
+        let perm_z = perlin_generate_perm(&mut rng);
+
+        Perlin {
+            ranvec,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    /// Smooth noise in roughly [-1, 1].
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
+
+        let i = p.x.floor() as i64;
+        let j = p.y.floor() as i64;
+        let k = p.z.floor() as i64;
+
+        let mut c = [[[Vec3::zero(); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize];
+                    *corner = self.ranvec[index];
+                }
+            }
+        }
+
+        perlin_interp(&c, u, v, w)
+    }
+
+    /// Sum of `depth` octaves of noise, each twice the frequency and half the
+    /// amplitude of the one before. Always positive.
+    pub fn turb(&self, p: &Point3, depth: usize) -> f64 {
+        self.fbm(p, depth, true)
+    }
+
+    /// Like `turb` but keeps the sign of every octave, which gives softer,
+    /// billowy shapes.
+    pub fn fbm(&self, p: &Point3, depth: usize, absolute: bool) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+
+        for _ in 0..depth {
+            let n = self.noise(&temp_p);
+            accum += weight * if absolute { n.abs() } else { n };
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+
+        if absolute {
+            accum.abs()
+        } else {
+            accum
+        }
+    }
+}
+
+fn perlin_generate_perm(rng: &mut StdRng) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+
+    for i in (1..POINT_COUNT).rev() {
+        let target = rng.gen_range(0, i + 1);
+        p.swap(i, target);
+    }
+
+    p
+}
+
+fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+    // Hermite cubic to round off the interpolation.
+    let uu = u * u * (3.0 - 2.0 * u);
+    let vv = v * v * (3.0 - 2.0 * v);
+    let ww = w * w * (3.0 - 2.0 * w);
+    let mut accum = 0.0;
+
+    for (i, plane) in c.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, corner) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                let weight_v = Vec3::new(u - fi, v - fj, w - fk);
+                accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                    * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                    * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                    * corner.dot(&weight_v);
+            }
+        }
+    }
+
+    accum
+}
+
+#[test]
+fn test_perlin_is_deterministic() {
+    let a = Perlin::new(42);
+    let b = Perlin::new(42);
+    let c = Perlin::new(43);
+
+    let mut differs = false;
+    for i in 0..100 {
+        let p = Point3::new(0.37 * i as f64, 0.11 * i as f64, -0.23 * i as f64);
+        let n = a.noise(&p);
+        assert_eq!(n, b.noise(&p));
+        assert!((-1.0..=1.0).contains(&n));
+        assert!(a.turb(&p, 7) >= 0.0);
+        differs |= n != c.noise(&p);
+    }
+    assert!(differs);
+
+    // The noise is zero on the lattice points.
+    assert_eq!(a.noise(&Point3::new(3.0, -2.0, 5.0)), 0.0);
+}
@@ -0,0 +1,19 @@
+use super::Texture;
+use crate::vec3::{Color, Point3};
+
+#[derive(Clone, Copy)]
+pub struct SolidColor {
+    pub color_value: Color,
+}
+
+impl SolidColor {
+    pub fn new(color_value: Color) -> SolidColor {
+        SolidColor { color_value }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color_value
+    }
+}
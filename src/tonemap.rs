@@ -0,0 +1,267 @@
+//! Turning the light a render collects into colors a screen can show.
+//! Exposure scales the image first, then a tone mapping operator squeezes
+//! everything brighter than white into the displayable range.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::framebuffer::luminance;
+use crate::image::{linear_to_srgb, srgb_to_linear, Image};
+use crate::utils::{self, mix_seed};
+use crate::vec3::Color;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    /// Cuts off at white, so highlights clip.
+    Clamp,
+    /// `L / (1 + L)` on the luminance, keeping the hue. Never quite reaches
+    /// white.
+    Reinhard,
+    /// Stephen Hill's fit of the ACES reference rendering transform, with
+    /// a filmic contrast curve.
+    Aces,
+    /// Like Blender's AgX: a log encoding, a sigmoid, and colors that fade to
+    /// white as they get brighter instead of turning into pure primaries.
+    Agx,
+}
+
+impl Operator {
+    pub const ALL: [Operator; 4] = [
+        Operator::Clamp,
+        Operator::Reinhard,
+        Operator::Aces,
+        Operator::Agx,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Operator::Clamp => "clamp",
+            Operator::Reinhard => "reinhard",
+            Operator::Aces => "aces",
+            Operator::Agx => "agx",
+        }
+    }
+
+    /// Maps a linear color to a linear color between 0 and 1.
+    pub fn map(self, c: Color) -> Color {
+        match self {
+            Operator::Clamp => clamp(c),
+            Operator::Reinhard => {
+                let l = luminance(c);
+                if l <= 0.0 {
+                    Color::zero()
+                } else {
+                    clamp(c / (1.0 + l))
+                }
+            }
+            Operator::Aces => aces(c),
+            Operator::Agx => agx(c),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Operator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Operator, String> {
+        Operator::ALL
+            .iter()
+            .copied()
+            .find(|operator| operator.name() == s)
+            .ok_or_else(|| {
+                let names = Operator::ALL.map(|operator| operator.name());
+                format!(
+                    "unknown tone mapping `{}`, use one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Everything that happens to an image between rendering and saving it as 8
+/// bits per channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMap {
+    /// In stops, each one doubles the brightness.
+    pub exposure: f64,
+    pub operator: Operator,
+    /// Adds a little noise before quantizing, which hides banding in smooth
+    /// gradients.
+    pub dither: bool,
+}
+
+impl Default for ToneMap {
+    fn default() -> ToneMap {
+        ToneMap {
+            exposure: 0.0,
+            operator: Operator::Clamp,
+            dither: false,
+        }
+    }
+}
+
+impl ToneMap {
+    /// The image as it should be displayed, still linear so it can be saved
+    /// like any other. With `dither` the noise is added in sRGB, where it's
+    /// a fraction of a step of the 8-bit output.
+    pub fn apply(&self, image: &Image) -> Image {
+        let scale = 2f64.powf(self.exposure);
+        let mut mapped = image.clone();
+        for (i, pixel) in mapped.pixels.iter_mut().enumerate() {
+            let mut c = self.operator.map(*pixel * scale);
+            if self.dither {
+                // Triangular noise between -1 and 1 steps, the same every time.
+                let hash = mix_seed(0x5eed, i as u64);
+                let noise = ((hash >> 32) as f64 + (hash as u32) as f64) / (1u64 << 32) as f64;
+                let dither = |v: f64| {
+                    srgb_to_linear(utils::clamp(
+                        linear_to_srgb(v) + (noise - 1.0) / 255.0,
+                        0.0,
+                        1.0,
+                    ))
+                };
+                c = Color::new(dither(c.x), dither(c.y), dither(c.z));
+            }
+            *pixel = c;
+        }
+        mapped
+    }
+}
+
+fn clamp(c: Color) -> Color {
+    Color::new(
+        utils::clamp(c.x, 0.0, 1.0),
+        utils::clamp(c.y, 0.0, 1.0),
+        utils::clamp(c.z, 0.0, 1.0),
+    )
+}
+
+/// Multiplies a color by a matrix given row by row.
+fn transform(m: [[f64; 3]; 3], c: Color) -> Color {
+    Color::new(
+        m[0][0] * c.x + m[0][1] * c.y + m[0][2] * c.z,
+        m[1][0] * c.x + m[1][1] * c.y + m[1][2] * c.z,
+        m[2][0] * c.x + m[2][1] * c.y + m[2][2] * c.z,
+    )
+}
+
+fn aces(c: Color) -> Color {
+    // sRGB to the ACES working space, with the RRT's saturation change.
+    const INPUT: [[f64; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f64; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let fit = |v: f64| {
+        let v = v.max(0.0);
+        (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.4329510) + 0.238081)
+    };
+
+    let v = transform(INPUT, c);
+    clamp(transform(OUTPUT, Color::new(fit(v.x), fit(v.y), fit(v.z))))
+}
+
+fn agx(c: Color) -> Color {
+    // Into a space whose primaries are pulled in towards white, so bright
+    // saturated colors desaturate on the way up.
+    const INSET: [[f64; 3]; 3] = [
+        [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+        [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+        [0.0423756549057051, 0.0784336, 0.879142973793104],
+    ];
+    const OUTSET: [[f64; 3]; 3] = [
+        [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+        [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+        [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+    ];
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+
+    let curve = |v: f64| {
+        // Log2 encoding over about 16.5 stops, then a polynomial fit of the
+        // AgX sigmoid, which comes out with gamma 2.2.
+        let x = (utils::clamp(v.max(1e-10).log2(), MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+
+    let v = transform(INSET, c);
+    let v = transform(OUTSET, Color::new(curve(v.x), curve(v.y), curve(v.z)));
+    let linear = |v: f64| utils::clamp(v, 0.0, 1.0).powf(2.2);
+    Color::new(linear(v.x), linear(v.y), linear(v.z))
+}
+
+#[test]
+fn test_tone_map() {
+    let gray = |v: f64| Color::new(v, v, v);
+
+    for operator in &Operator::ALL {
+        // Black stays black, brighter stays brighter and nothing goes past
+        // white.
+        let black = operator.map(Color::zero());
+        assert!(black.length() < 1e-3, "{} {:?}", operator, black);
+        let mut previous = black;
+        for i in 1..40 {
+            let c = operator.map(gray(0.01 * 1.5f64.powi(i)));
+            assert!(c.x >= previous.x - 1e-9, "{} {}", operator, i);
+            assert!(
+                c.x <= 1.0 && c.y <= 1.0 && c.z <= 1.0,
+                "{} {:?}",
+                operator,
+                c
+            );
+            previous = c;
+        }
+        assert_eq!(operator.name().parse(), Ok(*operator));
+    }
+    assert_eq!(Operator::Clamp.map(gray(3.0)), gray(1.0));
+    assert_eq!(Operator::Reinhard.map(gray(1.0)), gray(0.5));
+    // Middle gray stays in the middle.
+    for operator in &[Operator::Aces, Operator::Agx] {
+        let mid = operator.map(gray(0.18)).x;
+        assert!((0.1..0.25).contains(&mid), "{} {}", operator, mid);
+    }
+    // Very bright red fades to white with AgX, clips to red without.
+    let red = Color::new(100.0, 0.0, 0.0);
+    assert!(Operator::Agx.map(red).y > 0.5);
+    assert_eq!(Operator::Clamp.map(red), Color::new(1.0, 0.0, 0.0));
+
+    // One stop of exposure doubles the brightness.
+    let mut image = Image::new(64, 64);
+    image.pixels = vec![gray(0.2); 64 * 64];
+    let tone_map = ToneMap {
+        exposure: 1.0,
+        ..ToneMap::default()
+    };
+    assert_eq!(tone_map.apply(&image).pixels[0], gray(0.4));
+
+    // Dithering changes pixels by at most a step, but not on average.
+    let dithered = ToneMap {
+        dither: true,
+        ..tone_map
+    }
+    .apply(&image);
+    let encoded = linear_to_srgb(0.4);
+    let mut total = 0.0;
+    for pixel in &dithered.pixels {
+        let step = (linear_to_srgb(pixel.x) - encoded) * 255.0;
+        assert!(step.abs() <= 1.0);
+        total += step;
+    }
+    assert!((total / dithered.pixels.len() as f64).abs() < 0.02);
+    assert!(dithered.pixels.iter().any(|p| *p != gray(0.4)));
+}
@@ -0,0 +1,251 @@
+use crate::vec3::{Point3, Vec3};
+
+/// A 4x4 matrix, stored row by row. Points are column vectors, so `a * b`
+/// applies `b` first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Matrix4 { m }
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4 { m }
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting. Returns `None` if the
+    /// matrix is singular, e.g. a scale by zero.
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.m;
+        let mut inv = Matrix4::identity().m;
+
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Matrix4 { m: inv })
+    }
+
+    #[inline]
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 {
+            Point3::new(x, y, z)
+        } else {
+            Point3::new(x, y, z) / w
+        }
+    }
+
+    /// Directions ignore the translation part.
+    #[inline]
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl std::ops::Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Matrix4 { m }
+    }
+}
+
+/// An affine transform together with its inverse, which is what is needed
+/// to move rays into object space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub matrix: Matrix4,
+    pub inverse: Matrix4,
+    /// For normals, kept so it isn't worked out again for every hit.
+    inverse_transpose: Matrix4,
+}
+
+impl Transform {
+    /// Returns `None` if the matrix can't be inverted.
+    pub fn new(matrix: Matrix4) -> Option<Transform> {
+        matrix
+            .inverse()
+            .map(|inverse| Transform::with_inverse(matrix, inverse))
+    }
+
+    fn with_inverse(matrix: Matrix4, inverse: Matrix4) -> Transform {
+        Transform {
+            matrix,
+            inverse,
+            inverse_transpose: inverse.transpose(),
+        }
+    }
+
+    pub fn identity() -> Transform {
+        Transform::with_inverse(Matrix4::identity(), Matrix4::identity())
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        let mut matrix = Matrix4::identity();
+        let mut inverse = Matrix4::identity();
+        for a in 0..3 {
+            matrix.m[a][3] = offset[a];
+            inverse.m[a][3] = -offset[a];
+        }
+        Transform::with_inverse(matrix, inverse)
+    }
+
+    /// Panics if any of the factors is zero.
+    pub fn scale(factors: Vec3) -> Transform {
+        assert!(
+            factors.x != 0.0 && factors.y != 0.0 && factors.z != 0.0,
+            "can't scale by zero"
+        );
+        let mut matrix = Matrix4::identity();
+        let mut inverse = Matrix4::identity();
+        for a in 0..3 {
+            matrix.m[a][a] = factors[a];
+            inverse.m[a][a] = 1.0 / factors[a];
+        }
+        Transform::with_inverse(matrix, inverse)
+    }
+
+    /// Counterclockwise rotation around `axis` when looking down the axis
+    /// towards the origin.
+    pub fn rotate(axis: Vec3, degrees: f64) -> Transform {
+        let a = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+
+        let mut matrix = Matrix4::identity();
+        matrix.m[0][0] = a.x * a.x + (1.0 - a.x * a.x) * cos;
+        matrix.m[0][1] = a.x * a.y * (1.0 - cos) - a.z * sin;
+        matrix.m[0][2] = a.x * a.z * (1.0 - cos) + a.y * sin;
+        matrix.m[1][0] = a.x * a.y * (1.0 - cos) + a.z * sin;
+        matrix.m[1][1] = a.y * a.y + (1.0 - a.y * a.y) * cos;
+        matrix.m[1][2] = a.y * a.z * (1.0 - cos) - a.x * sin;
+        matrix.m[2][0] = a.x * a.z * (1.0 - cos) - a.y * sin;
+        matrix.m[2][1] = a.y * a.z * (1.0 - cos) + a.x * sin;
+        matrix.m[2][2] = a.z * a.z + (1.0 - a.z * a.z) * cos;
+
+        // Rotations are orthogonal, the inverse is the transpose.
+        Transform::with_inverse(matrix, matrix.transpose())
+    }
+
+    pub fn rotate_x(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new(1.0, 0.0, 0.0), degrees)
+    }
+
+    pub fn rotate_y(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new(0.0, 1.0, 0.0), degrees)
+    }
+
+    pub fn rotate_z(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new(0.0, 0.0, 1.0), degrees)
+    }
+
+    /// `self` followed by `next`.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform::with_inverse(next.matrix * self.matrix, self.inverse * next.inverse)
+    }
+
+    #[inline]
+    pub fn point(&self, p: &Point3) -> Point3 {
+        self.matrix.transform_point(p)
+    }
+
+    #[inline]
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+
+    /// Normals are transformed by the inverse transpose, so they stay
+    /// perpendicular to the surface under non-uniform scaling. The result
+    /// isn't normalized.
+    #[inline]
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        self.inverse_transpose.transform_vector(n)
+    }
+}
+
+#[test]
+fn test_transform() {
+    let close = |a: Vec3, b: Vec3| assert!((a - b).length() < 1e-12, "{:?} != {:?}", a, b);
+
+    let t = Transform::scale(Vec3::new(2.0, 1.0, 1.0))
+        .then(&Transform::rotate_z(90.0))
+        .then(&Transform::translate(Vec3::new(0.0, 0.0, 5.0)));
+
+    close(
+        t.point(&Point3::new(1.0, 0.0, 0.0)),
+        Point3::new(0.0, 2.0, 5.0),
+    );
+    close(
+        t.vector(&Vec3::new(1.0, 0.0, 0.0)),
+        Vec3::new(0.0, 2.0, 0.0),
+    );
+    close(
+        t.inverse.transform_point(&Point3::new(0.0, 2.0, 5.0)),
+        Point3::new(1.0, 0.0, 0.0),
+    );
+    close(
+        (t.matrix * t.matrix.inverse().unwrap()).transform_point(&Point3::new(1.0, 2.0, 3.0)),
+        Point3::new(1.0, 2.0, 3.0),
+    );
+
+    // A normal of the plane x + y = 0, squashed along x, must stay
+    // perpendicular to the plane.
+    let squash = Transform::scale(Vec3::new(0.5, 1.0, 1.0));
+    let n = squash.normal(&Vec3::new(1.0, 1.0, 0.0));
+    let in_plane = squash.vector(&Vec3::new(1.0, -1.0, 0.0));
+    assert!(n.dot(&in_plane).abs() < 1e-12);
+
+    let singular = Matrix4 { m: [[0.0; 4]; 4] };
+    assert!(Transform::new(singular).is_none());
+}
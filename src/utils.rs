@@ -1,4 +1,5 @@
-use rand::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng as _, SeedableRng};
 
 #[inline]
 pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
@@ -11,14 +12,47 @@ pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     }
 }
 
-pub fn random() -> f64 {
-    let mut rng = thread_rng();
+/// Combines a seed with an index (e.g. a pixel) into a new, unrelated seed.
+pub fn mix_seed(seed: u64, index: u64) -> u64 {
+    // splitmix64 finalizer
+    let mut z = seed ^ index.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Random numbers, passed along to everything that needs some. A render
+/// gives every pixel its own, seeded from the render's seed and the pixel,
+/// so the image only depends on the seed and not on which thread renders
+/// which pixel.
+pub struct Rng(StdRng);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(StdRng::seed_from_u64(seed))
+    }
 
-    rng.gen()
+    /// In [0, 1).
+    #[inline]
+    pub fn random(&mut self) -> f64 {
+        self.0.gen()
+    }
+
+    /// In [min, max).
+    #[inline]
+    pub fn random_range(&mut self, min: f64, max: f64) -> f64 {
+        self.0.gen_range(min, max)
+    }
 }
 
-pub fn random_range(min: f64, max: f64) -> f64 {
-    let mut rng = thread_rng();
+#[test]
+fn test_rng() {
+    let mut a = Rng::new(42);
+    let mut b = Rng::new(42);
+    let first = (a.random(), a.random_range(-1.0, 1.0));
+    assert_eq!(first, (b.random(), b.random_range(-1.0, 1.0)));
+    assert!(first.0 >= 0.0 && first.0 < 1.0);
+    assert!(first.1 >= -1.0 && first.1 < 1.0);
 
-    rng.gen_range(min, max)
+    assert_ne!(mix_seed(42, 0), mix_seed(42, 1));
 }
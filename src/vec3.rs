@@ -1,4 +1,4 @@
-use crate::utils::{random, random_range};
+use crate::sampler::Sampler;
 type Float = f64;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -12,53 +12,48 @@ pub type Color = Vec3;
 pub type Point3 = Vec3;
 
 impl Vec3 {
-    pub fn new(x: Float, y: Float, z: Float) -> Vec3 {
-        return Vec3 { x, y, z };
+    pub const fn new(x: Float, y: Float, z: Float) -> Vec3 {
+        Vec3 { x, y, z }
     }
 
     pub fn zero() -> Vec3 {
-        return Vec3::new(0.0, 0.0, 0.0);
+        Vec3::new(0.0, 0.0, 0.0)
     }
 
     pub fn ones() -> Vec3 {
-        return Vec3::new(1.0, 1.0, 1.0);
+        Vec3::new(1.0, 1.0, 1.0)
     }
 
-    pub fn random() -> Vec3 {
-        let x = random();
-        let y = random();
-        let z = random();
+    pub fn random(sampler: &mut dyn Sampler) -> Vec3 {
+        let x = sampler.get_1d();
+        let y = sampler.get_1d();
+        let z = sampler.get_1d();
 
-        return Vec3::new(x, y, z);
+        Vec3::new(x, y, z)
     }
 
-    pub fn random_range(min: f64, max: f64) -> Vec3 {
-        let x = random_range(min, max);
-        let y = random_range(min, max);
-        let z = random_range(min, max);
-
-        return Vec3::new(x, y, z);
+    pub fn random_range(sampler: &mut dyn Sampler, min: f64, max: f64) -> Vec3 {
+        Vec3::new(min, min, min) + (max - min) * Vec3::random(sampler)
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
-        return loop {
-            let p = Vec3::random_range(-1.0, 1.0);
-            if p.length_squared() < 1.0 {
-                break p;
-            }
-        };
+    /// Uses three dimensions of the sampler, whichever point it lands on:
+    /// a direction, then the distance from the center.
+    pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = Vec3::random_unit_vector(sampler);
+        sampler.get_1d().cbrt() * direction
     }
 
-    pub fn random_unit_vector() -> Vec3 {
-        let a = random_range(0.0, 2.0 * std::f64::consts::PI);
-        let z = random_range(-1.0, 1.0);
+    pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let a = 2.0 * std::f64::consts::PI * u;
+        let z = 1.0 - 2.0 * v;
         let r = (1.0 - z * z).sqrt();
         Vec3::new(r * a.cos(), r * a.sin(), z)
     }
 
-    pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
-        let in_unit_sphere = Vec3::random_in_unit_sphere();
-        if in_unit_sphere.dot(&normal) > 0.0 {
+    pub fn random_in_hemisphere(sampler: &mut dyn Sampler, normal: &Vec3) -> Vec3 {
+        let in_unit_sphere = Vec3::random_in_unit_sphere(sampler);
+        if in_unit_sphere.dot(normal) > 0.0 {
             // In the same hemisphere as the normal.
             in_unit_sphere
         } else {
@@ -66,37 +61,54 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_disk() -> Vec3 {
-        loop {
-            let p = Vec3::new(random_range(-1.0, 1.0), random_range(-1.0, 1.0), 0.0);
-            if p.length_squared() < 1.0 {
-                break p;
-            }
+    /// Random direction around +z, with a density proportional to the cosine
+    /// of the angle to z.
+    pub fn random_cosine_direction(sampler: &mut dyn Sampler) -> Vec3 {
+        let (r1, r2) = sampler.get_2d();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let r = r2.sqrt();
+        Vec3::new(phi.cos() * r, phi.sin() * r, (1.0 - r2).sqrt())
+    }
+
+    /// Maps the square onto the disk in concentric rings (Shirley and Chiu),
+    /// so samples spread evenly over the square stay even on the disk.
+    pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let (x, y) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        if x == 0.0 && y == 0.0 {
+            return Vec3::zero();
         }
+        let quarter = std::f64::consts::FRAC_PI_4;
+        let (r, theta) = if x.abs() > y.abs() {
+            (x, quarter * (y / x))
+        } else {
+            (y, 2.0 * quarter - quarter * (x / y))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
     }
 
     pub fn refract(&self, normal: &Vec3, etai_over_etat: f64) -> Vec3 {
         let uv = *self;
-        let cos_theta = -uv.dot(&normal);
+        let cos_theta = -uv.dot(normal);
         let r_out_perp = etai_over_etat * (uv + cos_theta * *normal);
         let r_out_parallel = -(1.0 - r_out_perp.length_squared()).abs().sqrt() * *normal;
         r_out_perp + r_out_parallel
     }
 
     pub fn reflect(&self, normal: &Vec3) -> Vec3 {
-        return *self - 2.0 * self.dot(normal) * *normal;
+        *self - 2.0 * self.dot(normal) * *normal
     }
 
     #[inline]
     /// I would have called this `magnitude` but this is what the book
     /// I am following is using.
     pub fn length(&self) -> Float {
-        return self.length_squared().sqrt();
+        self.length_squared().sqrt()
     }
 
     #[inline]
     pub fn length_squared(&self) -> Float {
-        return self.x * self.x + self.y * self.y + self.z * self.z;
+        self.x * self.x + self.y * self.y + self.z * self.z
     }
 
     #[inline]
@@ -150,11 +162,11 @@ impl std::ops::Add<Vec3> for Vec3 {
     type Output = Vec3;
 
     fn add(self, rhz: Vec3) -> Vec3 {
-        return Vec3 {
+        Vec3 {
             x: self.x + rhz.x,
             y: self.y + rhz.y,
             z: self.z + rhz.z,
-        };
+        }
     }
 }
 
@@ -162,11 +174,11 @@ impl std::ops::Sub<Vec3> for Vec3 {
     type Output = Vec3;
 
     fn sub(self, rhz: Vec3) -> Vec3 {
-        return Vec3 {
+        Vec3 {
             x: self.x - rhz.x,
             y: self.y - rhz.y,
             z: self.z - rhz.z,
-        };
+        }
     }
 }
 
@@ -226,7 +238,7 @@ impl std::ops::Neg for Vec3 {
     type Output = Vec3;
 
     fn neg(self) -> Vec3 {
-        return Vec3::new(-self.x, -self.y, -self.z);
+        Vec3::new(-self.x, -self.y, -self.z)
     }
 }
 